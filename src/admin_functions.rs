@@ -17,6 +17,7 @@ use crate::{
 };
 
 use super::{TEMPLATES};
+use crate::caching::forget_all_renders;
 
 
 pub fn watch_and_update_files() -> bool {
@@ -180,6 +181,7 @@ pub async fn reload_templates_request(req: HttpRequest) -> HttpResponse {
                 ":( the templates were not reloaded, trouble is afoot.",
             );
         }
+        forget_all_renders();
         println!("templates reloaded!");
         return responses::Accepted("templates succesfully reloaded");
     }
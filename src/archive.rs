@@ -0,0 +1,345 @@
+use actix_multipart::Multipart;
+use actix_web::{get, http::header, post, web, HttpRequest, HttpResponse};
+use futures::{StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use sled::IVec;
+use time::Duration;
+
+use std::{
+  convert::TryInto,
+  io::{Cursor, Read},
+};
+
+use crate::{
+  orchestrator::{Orchestrator, ORC},
+  responses,
+  utils::FancyIVec,
+  writs::{RawWrit, Writ, WritError, WritID},
+};
+
+const MAX_IMPORT_SIZE: usize = 50 * 1024 * 1024;
+const MAX_WRIT_LENGTH: usize = 200_000;
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct FrontMatter {
+  pub title: String,
+  #[serde(default)]
+  pub slug: String, // informational, imports get their slug from the title like any other writ
+  pub kind: String,
+  #[serde(default)]
+  pub tags: Vec<String>,
+  pub posted: i64,
+  pub public: bool,
+  pub commentable: bool,
+  #[serde(default)]
+  pub viewable_by: Vec<String>,
+  #[serde(default = "default_is_md")]
+  pub is_md: bool,
+}
+
+fn default_is_md() -> bool {
+  true
+}
+
+impl FrontMatter {
+  pub fn of(writ: &Writ) -> Self {
+    Self {
+      title: writ.title.clone(),
+      slug: writ.slug.clone(),
+      kind: writ.kind.clone(),
+      tags: writ.tags.clone(),
+      posted: writ.posted,
+      public: writ.public,
+      commentable: writ.commentable,
+      viewable_by: writ.viewable_by.clone(),
+      is_md: writ.is_md,
+    }
+  }
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct ImportedWrit {
+  pub file: String,
+  pub id: String,
+  pub title: String,
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct ImportFailure {
+  pub file: String,
+  pub error: String, // the WritError's code, or BadFrontMatter/TooLong when it never got that far
+  pub message: String,
+}
+
+impl ImportFailure {
+  fn new(file: &str, error: &str, message: &str) -> Self {
+    Self {
+      file: file.to_string(),
+      error: error.to_string(),
+      message: message.to_string(),
+    }
+  }
+
+  fn from_writ_error(file: &str, e: &WritError) -> Self {
+    Self::new(file, e.code(), &e.to_string())
+  }
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug, Default)]
+pub struct ImportReport {
+  pub imported: Vec<ImportedWrit>,
+  pub failed: Vec<ImportFailure>,
+}
+
+impl Orchestrator {
+  // writ ids lead with their kind and then their author, so this hops from one kind to the next
+  // and only ever reads the author's own stretch of each
+  pub fn author_writ_ids(&self, author_id: u64) -> Vec<IVec> {
+    let mut ids: Vec<IVec> = vec![];
+    let mut next_kind: Option<[u8; 4]> = Some([0; 4]);
+
+    while let Some(from) = next_kind {
+      let kind: [u8; 4] = match self.writs.range(from..).keys().next() {
+        Some(Ok(key)) if key.len() == 20 => key[0..4].try_into().unwrap(),
+        _ => break,
+      };
+
+      let mut prefix = kind.to_vec();
+      prefix.extend_from_slice(&author_id.to_be_bytes());
+      ids.extend(self.writs.scan_prefix(&prefix).keys().filter_map(|res| res.ok()));
+
+      next_kind = u32::from_be_bytes(kind).checked_add(1).map(u32::to_be_bytes);
+    }
+    ids
+  }
+
+  // every writ an author has, drafts and scheduled ones included, oldest first
+  pub fn author_writs(&self, author_id: u64) -> Vec<Writ> {
+    let mut writs: Vec<Writ> = self.author_writ_ids(author_id)
+      .iter()
+      .filter_map(|wid| self.writ_by_id_bytes(wid))
+      .collect();
+    writs.sort_by_key(|w| w.posted);
+    writs
+  }
+
+  // markdown writs hand back what was written, html ones what was stored
+  pub fn writ_source(&self, writ: &Writ) -> Option<String> {
+    let wid = WritID::from_str(&writ.id)?.to_bin();
+    let tree = if writ.is_md { &self.raw_content } else { &self.content };
+    match tree.get(&wid) {
+      Ok(Some(raw)) => Some(raw.to_string()),
+      _ => None,
+    }
+  }
+
+  // a single writ as a tar entry, so an export can go out one writ at a time,
+  // the id is in the file name since slugs only have to be unique per kind for now
+  pub fn export_entry(&self, wid: &[u8]) -> Option<Vec<u8>> {
+    let writ = self.writ_by_id_bytes(wid)?;
+    let source = self.writ_source(&writ)?;
+    let front_matter = toml::to_string(&FrontMatter::of(&writ)).ok()?;
+
+    let file = format!("+++\n{}+++\n\n{}\n", front_matter, source);
+    let mut head = tar::Header::new_gnu();
+    head.set_size(file.len() as u64);
+    head.set_mode(0o644);
+    head.set_mtime(writ.posted.max(0) as u64);
+    head.set_cksum();
+
+    let path = format!("{}/{}-{}.md", writ.kind, WritID::from_bin(wid).id, writ.slug);
+    let mut entry = tar::Builder::new(Vec::new());
+    entry.append_data(&mut head, path, file.as_bytes()).ok()?;
+    // taken out before the builder drops, dropping it tacks on an end of archive marker
+    Some(std::mem::take(entry.get_mut()))
+  }
+
+  pub fn import_writ_file(&self, author_id: u64, file: &str, text: &str, report: &mut ImportReport) {
+    let (front_matter, body) = match split_front_matter(text) {
+      Some(parts) => parts,
+      None => {
+        report.failed.push(ImportFailure::new(file, "BadFrontMatter", "files need +++ delimited toml front matter"));
+        return;
+      }
+    };
+
+    let fm: FrontMatter = match toml::from_str(front_matter) {
+      Ok(fm) => fm,
+      Err(e) => {
+        report.failed.push(ImportFailure::new(file, "BadFrontMatter", &e.to_string()));
+        return;
+      }
+    };
+
+    if body.len() > MAX_WRIT_LENGTH {
+      report.failed.push(ImportFailure::new(file, "TooLong", "writs have to be less than 200k characters"));
+      return;
+    }
+
+    let rw = RawWrit {
+      id: None,
+      title: fm.title,
+      raw_content: body.to_string(),
+      kind: fm.kind,
+      tags: fm.tags,
+      public: fm.public,
+      commentable: Some(fm.commentable),
+      viewable_by: Some(fm.viewable_by),
+      is_md: Some(fm.is_md),
+      publish_at: None,
+      force_slug: None,
+      skip_sanitize: None,
+      posted: Some(fm.posted),
+    };
+
+    match rw.commit(author_id) {
+      Ok(writ) => report.imported.push(ImportedWrit {
+        file: file.to_string(),
+        id: writ.id,
+        title: writ.title,
+      }),
+      Err(e) => report.failed.push(ImportFailure::from_writ_error(file, &e)),
+    }
+  }
+
+  pub fn import_writ_archive(&self, author_id: u64, file: &str, bytes: &[u8], report: &mut ImportReport) {
+    let mut archive = tar::Archive::new(Cursor::new(bytes));
+    let entries = match archive.entries() {
+      Ok(entries) => entries,
+      Err(e) => {
+        report.failed.push(ImportFailure::new(file, "BadArchive", &e.to_string()));
+        return;
+      }
+    };
+
+    for entry in entries {
+      let mut entry = match entry {
+        Ok(entry) => entry,
+        Err(e) => {
+          report.failed.push(ImportFailure::new(file, "BadArchive", &e.to_string()));
+          return;
+        }
+      };
+
+      let path = match entry.path() {
+        Ok(path) => format!("{}/{}", file, path.to_string_lossy()),
+        Err(_) => continue,
+      };
+      if !path.ends_with(".md") {
+        continue;
+      }
+
+      let mut text = String::new();
+      if entry.read_to_string(&mut text).is_err() {
+        report.failed.push(ImportFailure::new(&path, "BadEncoding", "writ files have to be utf-8"));
+        continue;
+      }
+      self.import_writ_file(author_id, &path, &text, report);
+    }
+  }
+}
+
+pub fn split_front_matter(text: &str) -> Option<(&str, &str)> {
+  let text = text.trim_start_matches('\u{feff}').trim_start();
+  let rest = text.strip_prefix("+++")?.trim_start_matches('\r').strip_prefix('\n')?;
+  let end = rest.find("\n+++")?;
+  let front_matter = &rest[..end + 1];
+  let body = rest[end + 4..].trim();
+  Some((front_matter, body))
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct ExportQuery {
+  pub author_id: Option<u64>,
+}
+
+#[get("/writs/export")]
+pub async fn export_writs(req: HttpRequest, query: web::Query<ExportQuery>) -> HttpResponse {
+  let usr = match ORC.user_by_session(&req) {
+    Some(usr) => usr,
+    None => return responses::Forbidden("you have to be logged in to export writs"),
+  };
+
+  // admins can back up anyone, everyone else only themselves
+  let author_id = match query.author_id {
+    Some(id) if id != usr.id && !ORC.is_admin(usr.id) => {
+      return responses::Forbidden("you may only export your own writs");
+    },
+    Some(id) => id,
+    None => usr.id,
+  };
+
+  let entries = ORC.author_writ_ids(author_id)
+    .into_iter()
+    .filter_map(|wid| ORC.export_entry(&wid))
+    // two empty blocks is how a tar archive ends
+    .chain(std::iter::once(vec![0u8; 1024]))
+    .map(|chunk| Ok::<_, actix_web::Error>(web::Bytes::from(chunk)));
+
+  HttpResponse::Ok()
+    .content_type("application/x-tar")
+    .append_header((
+      header::CONTENT_DISPOSITION,
+      format!("attachment; filename=\"writs-{}.tar\"", author_id),
+    ))
+    .streaming(futures::stream::iter(entries))
+}
+
+// takes the archives export hands out as well as loose .md files
+#[post("/writs/import")]
+pub async fn import_writs(req: HttpRequest, mut payload: Multipart) -> HttpResponse {
+  let usr_id = match ORC.user_id_by_session(&req) {
+    Some(id) => id,
+    None => return responses::Forbidden("you have to be logged in to import writs"),
+  };
+
+  if !ORC.user_has_some_attrs(usr_id, &["writer", "admin"]).unwrap_or(false) {
+    return responses::Forbidden("only writers may import writs");
+  }
+
+  let hitter = format!("impt{}", usr_id);
+  if let Some(rl) = ORC.ratelimiter
+    .hit(hitter.as_bytes(), 5, Duration::minutes(30))
+  {
+    if rl.is_timing_out() {
+      return responses::TooManyRequests(format!(
+        "too many imports, timeout has {} minutes left.",
+        rl.minutes_left()
+      ));
+    }
+  }
+
+  let mut report = ImportReport::default();
+  let mut total = 0;
+
+  while let Ok(Some(mut field)) = payload.try_next().await {
+    let file = field
+      .content_disposition()
+      .and_then(|cd| cd.get_filename().map(|name| name.to_string()))
+      .unwrap_or_else(|| "unnamed".to_string());
+
+    let mut bytes: Vec<u8> = vec![];
+    while let Some(chunk) = field.next().await {
+      let chunk = match chunk {
+        Ok(chunk) => chunk,
+        Err(_) => return responses::BadRequest("the upload broke off halfway"),
+      };
+      total += chunk.len();
+      if total > MAX_IMPORT_SIZE {
+        return responses::BadRequest("imports can't be bigger than 50MB");
+      }
+      bytes.extend_from_slice(&chunk);
+    }
+
+    if file.ends_with(".tar") {
+      ORC.import_writ_archive(usr_id, &file, &bytes, &mut report);
+    } else {
+      match String::from_utf8(bytes) {
+        Ok(text) => ORC.import_writ_file(usr_id, &file, &text, &mut report),
+        Err(_) => report.failed.push(ImportFailure::new(&file, "BadEncoding", "writ files have to be utf-8")),
+      }
+    }
+  }
+
+  responses::Ok(report)
+}
@@ -0,0 +1,116 @@
+use actix_web::{dev::HttpResponseBuilder, http::header, HttpRequest};
+use parking_lot::RwLock;
+
+use std::{collections::HashMap, lazy::SyncLazy};
+
+use crate::{
+  orchestrator::ORC,
+  utils::{datetime_from_unix_timestamp, to_hex, unix_timestamp},
+};
+
+// pages show votes, comments and related writs, so even a cached render has to be checked on
+pub const PAGE_CACHE_CONTROL: &str = "public, no-cache";
+pub const PRIVATE_CACHE_CONTROL: &str = "private, no-cache";
+
+// renders older than this get redone, related writs and rankings drift without any commit
+const RENDER_MAX_AGE: i64 = 60 * 10;
+const RENDER_CACHE_SIZE: usize = 512;
+
+static RENDER_CACHE: SyncLazy<RwLock<HashMap<String, CachedRender>>> =
+  SyncLazy::new(|| RwLock::new(HashMap::new()));
+
+#[derive(Clone, Debug)]
+pub struct CachedRender {
+  pub html: String,
+  pub etag: String,
+  pub rendered: i64,
+}
+
+pub fn etag_of(bytes: &[u8]) -> String {
+  format!("\"{}\"", to_hex(&ORC.hash(bytes)[..16]))
+}
+
+pub fn http_date(timestamp: i64) -> String {
+  datetime_from_unix_timestamp(timestamp).format("%a, %d %b %Y %H:%M:%S GMT")
+}
+
+// weak and strong tags are the same thing to us, the body is all there is
+pub fn is_fresh(req: &HttpRequest, etag: &str) -> bool {
+  match req.headers().get(header::IF_NONE_MATCH).and_then(|inm| inm.to_str().ok()) {
+    Some(inm) => inm
+      .split(',')
+      .map(|tag| tag.trim().trim_start_matches("W/"))
+      .any(|tag| tag == etag || tag == "*"),
+    None => false,
+  }
+}
+
+pub fn add_validators<'a>(
+  res: &'a mut HttpResponseBuilder,
+  etag: &str,
+  last_modified: Option<i64>,
+  cache_control: &str,
+) -> &'a mut HttpResponseBuilder {
+  res.append_header((header::ETAG, etag.to_string()));
+  res.append_header((header::CACHE_CONTROL, cache_control.to_string()));
+  if let Some(lm) = last_modified {
+    res.append_header((header::LAST_MODIFIED, http_date(lm)));
+  }
+  res
+}
+
+pub fn cached_render(writ_id: &str) -> Option<CachedRender> {
+  let cache = RENDER_CACHE.read();
+  let cached = cache.get(writ_id)?;
+  if unix_timestamp() - cached.rendered > RENDER_MAX_AGE {
+    return None;
+  }
+  Some(cached.clone())
+}
+
+pub fn cache_render(writ_id: &str, html: String, etag: String) {
+  let now = unix_timestamp();
+  let mut cache = RENDER_CACHE.write();
+  if cache.len() >= RENDER_CACHE_SIZE {
+    cache.retain(|_, cached| now - cached.rendered <= RENDER_MAX_AGE);
+    if cache.len() >= RENDER_CACHE_SIZE {
+      cache.clear();
+    }
+  }
+  cache.insert(writ_id.to_string(), CachedRender { html, etag, rendered: now });
+}
+
+pub fn forget_render(writ_id: &str) {
+  RENDER_CACHE.write().remove(writ_id);
+}
+
+pub fn forget_all_renders() {
+  RENDER_CACHE.write().clear();
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use actix_web::test::TestRequest;
+
+  fn with_inm(inm: &str) -> HttpRequest {
+    TestRequest::default().insert_header((header::IF_NONE_MATCH, inm)).to_http_request()
+  }
+
+  #[test]
+  fn fresh_when_any_listed_tag_matches() {
+    let etag = "\"abc123\"";
+    assert!(is_fresh(&with_inm("\"abc123\""), etag));
+    assert!(is_fresh(&with_inm("W/\"abc123\""), etag));
+    assert!(is_fresh(&with_inm("\"old\", W/\"abc123\""), etag));
+    assert!(is_fresh(&with_inm("*"), etag));
+  }
+
+  #[test]
+  fn stale_without_a_matching_tag() {
+    let etag = "\"abc123\"";
+    assert!(!is_fresh(&TestRequest::default().to_http_request(), etag));
+    assert!(!is_fresh(&with_inm("\"old\""), etag));
+    assert!(!is_fresh(&with_inm("abc123"), etag));
+  }
+}
@@ -0,0 +1,272 @@
+use actix_web::{delete, get, put, web, HttpRequest, HttpResponse};
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+use sled::{transaction::*, Transactional};
+
+use crate::{
+  caching::forget_render,
+  orchestrator::{Orchestrator, ORC},
+  responses,
+  utils::{unix_timestamp, FancyIVec},
+  writs::WritID,
+};
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum CollaboratorRole {
+  Editor,   // may edit the writ
+  CoAuthor, // may edit it and is named in the byline
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Collaborator {
+  pub user_id: u64,
+  pub role: CollaboratorRole,
+  pub added: i64,
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct PublicCollaborator {
+  pub user_id: u64,
+  pub name: String,
+  pub handle: String,
+  pub role: CollaboratorRole,
+  pub added: i64,
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct CoAuthor {
+  pub name: String,
+  pub handle: String,
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct CollaboratorRequest {
+  pub handle: String,
+  pub role: CollaboratorRole,
+}
+
+impl Orchestrator {
+  pub fn writ_collaborators(&self, wid: &[u8]) -> Vec<Collaborator> {
+    match self.writ_collaborators.get(wid) {
+      Ok(Some(raw)) => BorshDeserialize::try_from_slice(&raw).unwrap(),
+      _ => vec![],
+    }
+  }
+
+  pub fn collaborator_role(&self, usr_id: u64, wid: &[u8]) -> Option<CollaboratorRole> {
+    match self.user_collaborations.get(user_collaboration_key(usr_id, wid)) {
+      Ok(Some(raw)) => Some(CollaboratorRole::try_from_slice(&raw).unwrap()),
+      _ => None,
+    }
+  }
+
+  // the author or anyone they've brought on
+  pub fn can_edit_writ(&self, usr_id: u64, writ_id: &WritID) -> bool {
+    writ_id.author == usr_id || self.collaborator_role(usr_id, &writ_id.to_bin()).is_some()
+  }
+
+  pub fn collaborating_writ_ids(&self, usr_id: u64) -> Vec<String> {
+    self.user_collaborations
+      .scan_prefix(usr_id.to_be_bytes())
+      .keys()
+      .filter_map(|res| res.ok())
+      .map(|key| WritID::from_bin(&key[8..]).to_string())
+      .collect()
+  }
+
+  pub fn co_authors(&self, wid: &[u8]) -> Vec<CoAuthor> {
+    self.writ_collaborators(wid)
+      .iter()
+      .filter(|c| c.role == CollaboratorRole::CoAuthor)
+      .filter_map(|c| self.user_by_id(c.user_id))
+      .map(|usr| CoAuthor { name: usr.username, handle: usr.handle })
+      .collect()
+  }
+
+  pub fn public_collaborators(&self, wid: &[u8]) -> Vec<PublicCollaborator> {
+    self.writ_collaborators(wid)
+      .into_iter()
+      .filter_map(|c| {
+        let usr = self.user_by_id(c.user_id)?;
+        Some(PublicCollaborator {
+          user_id: c.user_id,
+          name: usr.username,
+          handle: usr.handle,
+          role: c.role,
+          added: c.added,
+        })
+      })
+      .collect()
+  }
+
+  // adds someone or changes their role
+  pub fn set_collaborator(&self, writ_id: &WritID, usr_id: u64, role: CollaboratorRole) -> bool {
+    let wid = writ_id.to_bin();
+    let res: TransactionResult<(), ()> = (&self.writs, &self.writ_collaborators, &self.user_collaborations)
+      .transaction(|(writs, writ_collaborators, user_collaborations)| {
+        if writs.get(&wid)?.is_none() {
+          return Err(ConflictableTransactionError::Abort(()));
+        }
+
+        let mut collaborators: Vec<Collaborator> = match writ_collaborators.get(&wid)? {
+          Some(raw) => BorshDeserialize::try_from_slice(&raw).unwrap(),
+          None => vec![],
+        };
+
+        match collaborators.iter_mut().find(|c| c.user_id == usr_id) {
+          Some(c) => c.role = role,
+          None => collaborators.push(Collaborator { user_id: usr_id, role, added: unix_timestamp() }),
+        }
+
+        writ_collaborators.insert(wid.as_slice(), collaborators.try_to_vec().unwrap())?;
+        user_collaborations.insert(user_collaboration_key(usr_id, &wid), role.try_to_vec().unwrap())?;
+        Ok(())
+      });
+    // co-authors are in the byline, so the cached page has to go either way
+    if res.is_ok() {
+      forget_render(&writ_id.to_string());
+    }
+    res.is_ok()
+  }
+
+  pub fn remove_collaborator(&self, writ_id: &WritID, usr_id: u64) -> bool {
+    let wid = writ_id.to_bin();
+    let res: TransactionResult<(), ()> = (&self.writ_collaborators, &self.user_collaborations)
+      .transaction(|(writ_collaborators, user_collaborations)| {
+        let mut collaborators: Vec<Collaborator> = match writ_collaborators.get(&wid)? {
+          Some(raw) => BorshDeserialize::try_from_slice(&raw).unwrap(),
+          None => return Err(ConflictableTransactionError::Abort(())),
+        };
+
+        let before = collaborators.len();
+        collaborators.retain(|c| c.user_id != usr_id);
+        if collaborators.len() == before {
+          return Err(ConflictableTransactionError::Abort(()));
+        }
+
+        if collaborators.is_empty() {
+          writ_collaborators.remove(wid.as_slice())?;
+        } else {
+          writ_collaborators.insert(wid.as_slice(), collaborators.try_to_vec().unwrap())?;
+        }
+        user_collaborations.remove(user_collaboration_key(usr_id, &wid))?;
+        Ok(())
+      });
+    if res.is_ok() {
+      forget_render(&writ_id.to_string());
+    }
+    res.is_ok()
+  }
+
+  pub fn remove_writ_collaborators_in_transaction(
+    &self,
+    writ_collaborators: &TransactionalTree,
+    user_collaborations: &TransactionalTree,
+    wid: &[u8],
+  ) -> ConflictableTransactionResult<(), ()> {
+    if let Some(raw) = writ_collaborators.remove(wid)? {
+      let collaborators: Vec<Collaborator> = BorshDeserialize::try_from_slice(&raw).unwrap();
+      for c in collaborators.iter() {
+        user_collaborations.remove(user_collaboration_key(c.user_id, wid))?;
+      }
+    }
+    Ok(())
+  }
+}
+
+#[inline]
+pub fn user_collaboration_key(usr_id: u64, wid: &[u8]) -> Vec<u8> {
+  let mut key = Vec::with_capacity(8 + wid.len());
+  key.extend_from_slice(&usr_id.to_be_bytes());
+  key.extend_from_slice(wid);
+  key
+}
+
+#[get("/writ/{id}/collaborators")]
+pub async fn list_writ_collaborators(
+  req: HttpRequest,
+  wid: web::Path<String>,
+) -> HttpResponse {
+  let usr = match ORC.user_by_session(&req) {
+    Some(usr) => usr,
+    None => return responses::Forbidden("you have to be logged in to see a writ's collaborators"),
+  };
+
+  let writ_id = match WritID::from_str(wid.as_str()) {
+    Some(writ_id) => writ_id,
+    None => return responses::BadRequest("bad writ id"),
+  };
+
+  if !ORC.can_edit_writ(usr.id, &writ_id) && !ORC.is_admin(usr.id) {
+    return responses::Forbidden("only the writ's author and collaborators may see who works on it");
+  }
+
+  responses::Ok(ORC.public_collaborators(&writ_id.to_bin()))
+}
+
+#[put("/writ/{id}/collaborators")]
+pub async fn set_writ_collaborator(
+  req: HttpRequest,
+  wid: web::Path<String>,
+  cr: web::Json<CollaboratorRequest>,
+) -> HttpResponse {
+  let usr = match ORC.user_by_session(&req) {
+    Some(usr) => usr,
+    None => return responses::Forbidden("you have to be logged in to add collaborators"),
+  };
+
+  let writ_id = match WritID::from_str(wid.as_str()) {
+    Some(writ_id) => writ_id,
+    None => return responses::BadRequest("bad writ id"),
+  };
+
+  if writ_id.author != usr.id && !ORC.is_admin(usr.id) {
+    return responses::Forbidden("only the writ's author may pick its collaborators");
+  }
+
+  let collaborator_id = match ORC.handles.get(cr.handle.as_bytes()) {
+    Ok(Some(id)) => id.to_u64(),
+    _ => return responses::NotFound("no user goes by that handle"),
+  };
+
+  if collaborator_id == writ_id.author {
+    return responses::BadRequest("the author already has every right to their writ");
+  }
+
+  if !ORC.user_has_some_attrs(collaborator_id, &["writer", "admin"]).unwrap_or(false) {
+    return responses::BadRequest("only writers can collaborate on writs");
+  }
+
+  if ORC.set_collaborator(&writ_id, collaborator_id, cr.role) {
+    return responses::Ok(ORC.public_collaborators(&writ_id.to_bin()));
+  }
+  responses::BadRequest("couldn't add the collaborator, does the writ exist?")
+}
+
+#[delete("/writ/{id}/collaborators/{user_id}")]
+pub async fn remove_writ_collaborator(
+  req: HttpRequest,
+  path: web::Path<(String, u64)>,
+) -> HttpResponse {
+  let usr = match ORC.user_by_session(&req) {
+    Some(usr) => usr,
+    None => return responses::Forbidden("you have to be logged in to remove collaborators"),
+  };
+
+  let (wid, collaborator_id) = path.into_inner();
+  let writ_id = match WritID::from_str(&wid) {
+    Some(writ_id) => writ_id,
+    None => return responses::BadRequest("bad writ id"),
+  };
+
+  // collaborators can always step down themselves
+  if writ_id.author != usr.id && collaborator_id != usr.id && !ORC.is_admin(usr.id) {
+    return responses::Forbidden("only the writ's author may remove its collaborators");
+  }
+
+  if ORC.remove_collaborator(&writ_id, collaborator_id) {
+    return responses::Accepted("collaborator removed");
+  }
+  responses::NotFound("that user isn't collaborating on the writ")
+}
@@ -9,8 +9,11 @@ use time::{Duration};
 
 use crate::{
   auth::User,
+  caching::forget_render,
   responses,
   orchestrator::ORC,
+  reactions::ReactionCount,
+  sanitizer::sanitize_html,
   utils::{
     datetime_from_unix_timestamp, i64_is_zero, render_md, unix_timestamp, FancyBool, FancyIVec,
   },
@@ -29,6 +32,10 @@ pub struct PublicComment {
   pub edited: Option<i64>,
   #[serde(skip_serializing_if = "Option::is_none")]
   pub you_voted: Option<bool>,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub reactions: Vec<ReactionCount>,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub you_reacted: Vec<String>,
   #[serde(skip_serializing_if = "Option::is_none")]
   pub author_only: Option<bool>,
 }
@@ -71,6 +78,12 @@ impl Comment {
     None
   }
 
+  // replies only know their own id, the writ they're under starts off their key path
+  pub fn parent_writ_id(&self) -> Option<WritID> {
+    let key_path = if self.is_root_comment() { None } else { self.key_path() };
+    writ_id_of_comment(&self.id, key_path.as_deref())
+  }
+
   pub fn new_first_level_id(writ_id: &str, usr_id: u64) -> Option<(String, String)> {
     if let Ok(uid) = ORC.generate_id(writ_id.as_bytes()) {
       let own_id = format!("{}:{}", usr_id, uid);
@@ -129,6 +142,8 @@ impl Comment {
         },
         None => None,
       },
+      reactions: ORC.reaction_counts(&ORC.comment_reactions, &self.id),
+      you_reacted: ORC.you_reacted(&ORC.comment_reactors, &self.id, usr_id),
       vote: if let Ok(Some(raw)) = ORC.comment_votes.get(self.id.as_bytes()) {
         raw.to_i64()
       } else {
@@ -180,11 +195,21 @@ impl Comment {
         Ok(())
       });
 
+    if res.is_ok() {
+      self.forget_writ_render();
+    }
     res.is_ok()
   }
 
+  // the writ's page shows how many comments it has, a cached render of it is stale now
+  pub fn forget_writ_render(&self) {
+    if let Some(writ_id) = self.parent_writ_id() {
+      forget_render(&writ_id.to_string());
+    }
+  }
+
   pub fn is_root_comment(&self) -> bool {
-    self.id.matches(":").count() > 1
+    is_root_comment_id(&self.id)
   }
 /*
   pub fn get_root_comment_id(&self) -> Option<String> {
@@ -244,7 +269,7 @@ impl Comment {
     raw_contents: &TransactionalTree,
     voters: &TransactionalTree,
     votes: &TransactionalTree,
-  ) -> ConflictableTransactionResult<(), ()> {
+  ) -> ConflictableTransactionResult<u64, ()> {
     let is_root_comment = self.is_root_comment();
     let (root_id, path) = if is_root_comment {
       let path: Vec<String> = self.id
@@ -270,6 +295,8 @@ impl Comment {
     comments.remove(self.id.as_bytes())?;
     raw_contents.remove(self.id.as_bytes())?;
     votes.remove(self.id.as_bytes())?;
+    // this comment along with however many replies went with it
+    let mut removed: u64 = 1;
 
     if let Some(raw) = ctrees.get(root_id.as_bytes())? {
       let mut parent_id_tree: CommentIDTree = bincode::deserialize(&raw).unwrap();
@@ -277,7 +304,7 @@ impl Comment {
         for cidtree in child_cidtree.children.values() {
           if let Some(raw_child_comment) = comments.get(cidtree.comment.as_bytes())? {
             let child_comment: Comment = Comment::try_from_slice(&raw_child_comment).unwrap();
-            child_comment.remove_in_transaction(
+            removed += child_comment.remove_in_transaction(
               kpi,
               ctrees,
               comments,
@@ -291,7 +318,7 @@ impl Comment {
         }
         if let Some(raw_child_comment) = comments.get(child_cidtree.comment.as_bytes())? {
           let child_comment = Comment::try_from_slice(&raw_child_comment).unwrap();
-          child_comment.remove_in_transaction(
+          removed += child_comment.remove_in_transaction(
             kpi,
             ctrees,
             comments,
@@ -319,26 +346,45 @@ impl Comment {
       }
       kpi.remove(self.id.as_bytes())?;
 
-      return Ok(());
+      return Ok(removed);
     }
 
     Err(sled::transaction::ConflictableTransactionError::Abort(()))
   }
 
   pub fn remove(&self) -> bool {
-    (
+    // the key path index entry goes with the comment, so the writ has to be found first
+    let writ_id = self.parent_writ_id();
+    let wid = writ_id.as_ref().map(|writ_id| writ_id.to_bin());
+
+    let removed = (
       &ORC.comment_key_path_index,
       &ORC.comment_trees,
       &ORC.comments,
       &ORC.comment_raw_content,
       &ORC.comment_voters,
       &ORC.comment_votes,
+      &ORC.writ_rankings,
+      &ORC.writ_ranks,
     )
-      .transaction(|(kpi, ctrees, comments, raw_contents, voters, votes)| {
-        self.remove_in_transaction(kpi, ctrees, comments, raw_contents, voters, votes)?;
+      .transaction(|(kpi, ctrees, comments, raw_contents, voters, votes, rankings, ranks)| {
+        let removed = self.remove_in_transaction(kpi, ctrees, comments, raw_contents, voters, votes)?;
+        if let Some(wid) = &wid {
+          ORC.update_writ_ranks_in_transaction(rankings, ranks, wid, |r| {
+            r.comments = r.comments.saturating_sub(removed)
+          })?;
+        }
         Ok(())
       })
-      .is_ok()
+      .is_ok();
+
+    if removed {
+      ORC.forget_reactions(&ORC.comment_reactions, &ORC.comment_reactors, &self.id);
+      if let Some(writ_id) = &writ_id {
+        forget_render(&writ_id.to_string());
+      }
+    }
+    removed
   }
 
   pub fn vote(&self, usr_id: u64, up: Option<bool>) -> Option<i64> {
@@ -442,7 +488,7 @@ pub fn comment_on_writ(
       }
     }
 
-    let content = render_md(&raw_content);
+    let content = sanitize_html(&render_md(&raw_content));
 
     let (id, _own_id) = match Comment::new_first_level_id(&writ.id, usr.id) {
       Some(i) => i,
@@ -452,13 +498,17 @@ pub fn comment_on_writ(
     let mut comment = Comment::new(id, usr.username.clone(), content);
     comment.author_only = author_only;
 
+    let wid = WritID::from_str(&writ.id)?.to_bin();
+
     let res: TransactionResult<(), ()> = (
       &ORC.comment_trees,
       &ORC.comments,
       &ORC.comment_raw_content,
       &ORC.comment_votes,
+      &ORC.writ_rankings,
+      &ORC.writ_ranks,
     )
-      .transaction(|(comment_trees, comments, comment_raw_content, votes)| {
+      .transaction(|(comment_trees, comments, comment_raw_content, votes, rankings, ranks)| {
         let cidtree = CommentIDTree {
           comment: comment.id.clone(),
           children: HashMap::new(),
@@ -468,10 +518,12 @@ pub fn comment_on_writ(
         comments.insert(comment.id.as_bytes(), comment.try_to_vec().unwrap())?;
         comment_raw_content.insert(comment.id.as_bytes(), raw_content.as_bytes())?;
         votes.insert(comment.id.as_bytes(), IVec::from_i64(0))?;
+        ORC.update_writ_ranks_in_transaction(rankings, ranks, &wid, |r| r.comments += 1)?;
         Ok(())
       });
 
     if res.is_ok() {
+      comment.forget_writ_render();
       return Some(comment);
     }
   }
@@ -512,7 +564,7 @@ pub fn comment_on_comment(
     }
   }
 
-  let content = render_md(&raw_content);
+  let content = sanitize_html(&render_md(&raw_content));
 
   let (id, own_id) = match Comment::new_subcomment_id(&writ_id, &parent_id, usr.id) {
     Some(i) => i,
@@ -522,14 +574,18 @@ pub fn comment_on_comment(
   let mut comment = Comment::new(own_id, usr.username.clone(), content);
   comment.author_only = author_only;
 
+  let wid = WritID::from_str(&writ_id)?.to_bin();
+
   if (
     &ORC.comment_key_path_index,
     &ORC.comment_trees,
     &ORC.comments,
     &ORC.comment_raw_content,
     &ORC.comment_votes,
+    &ORC.writ_rankings,
+    &ORC.writ_ranks,
   )
-    .transaction(|(kpi, comment_trees, comments, comment_raw_content, votes)| {
+    .transaction(|(kpi, comment_trees, comments, comment_raw_content, votes, rankings, ranks)| {
         if let Some(raw) = comment_trees.get(tree_id.as_bytes())? {
           let mut parent_id_tree: CommentIDTree = bincode::deserialize(&raw).unwrap();
           let id_tree = CommentIDTree {
@@ -553,11 +609,13 @@ pub fn comment_on_comment(
         comments.insert(comment.id.as_bytes(), comment.try_to_vec().unwrap())?;
         comment_raw_content.insert(comment.id.as_bytes(), raw_content.as_bytes())?;
         votes.insert(comment.id.as_bytes(), IVec::from_i64(0))?;
+        ORC.update_writ_ranks_in_transaction(rankings, ranks, &wid, |r| r.comments += 1)?;
         Ok(())
       },
     )
     .is_ok()
   {
+    comment.forget_writ_render();
     return Some(comment);
   }
   None
@@ -583,7 +641,7 @@ pub fn edit_comment(settings: &CommentSettings, rce: RawCommentEdit) -> Option<C
       if let Ok(Some(raw_comment)) = comments.get(rce.id.as_bytes()) {
         let mut comment = Comment::try_from_slice(&raw_comment).unwrap();
         comment.author_only = rce.author_only.unwrap_or(false);
-        comment.content = render_md(&rce.raw_content);
+        comment.content = sanitize_html(&render_md(&rce.raw_content));
         comment.edited = Some(unix_timestamp());
 
         comments.insert(rce.id.as_bytes(), comment.try_to_vec().unwrap())?;
@@ -593,6 +651,7 @@ pub fn edit_comment(settings: &CommentSettings, rce: RawCommentEdit) -> Option<C
       Err(sled::transaction::ConflictableTransactionError::Abort(()))
     })
   {
+    comment.forget_writ_render();
     return Some(comment);
   }
   None
@@ -1327,3 +1386,38 @@ fn get_prefix_and_parts(id: &str, prefix_parts: usize) -> (String, Vec<String>)
 
   (prefix, parts)
 }
+
+// root comments are {writ_id}/{usr}:{uid}, replies are just {usr}:{uid}
+fn is_root_comment_id(id: &str) -> bool {
+  id.matches(":").count() > 1
+}
+
+fn writ_id_of_comment(id: &str, key_path: Option<&str>) -> Option<WritID> {
+  let full_id = if is_root_comment_id(id) { id } else { key_path? };
+  WritID::from_str(full_id.split('/').next()?)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::caching::{cache_render, cached_render};
+
+  #[test]
+  fn root_comments_and_replies_lead_back_to_their_writ() {
+    let of = |id: &str, key_path: Option<&str>| writ_id_of_comment(id, key_path).map(|w| w.to_string());
+    assert_eq!(of("post:1:7/2:9", None), Some("post:1:7".to_string()));
+    assert_eq!(of("3:12", Some("post:1:7/2:9/3:12")), Some("post:1:7".to_string()));
+    // a reply whose key path is gone can't be placed anymore
+    assert_eq!(of("3:12", None), None);
+  }
+
+  #[test]
+  fn replies_evict_the_cached_page() {
+    cache_render("post:1:8", "<p>two comments</p>".to_string(), "\"etag\"".to_string());
+    assert!(cached_render("post:1:8").is_some());
+
+    let writ_id = writ_id_of_comment("4:20", Some("post:1:8/2:10/4:20")).unwrap();
+    forget_render(&writ_id.to_string());
+    assert!(cached_render("post:1:8").is_none());
+  }
+}
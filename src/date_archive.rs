@@ -0,0 +1,167 @@
+use actix_web::{get, web, HttpResponse};
+use borsh::BorshDeserialize;
+use serde::{Deserialize, Serialize};
+use sled::{transaction::*, IVec};
+
+use std::collections::BTreeMap;
+
+use crate::{
+  orchestrator::{Orchestrator, ORC},
+  responses,
+  utils::{unix_timestamp, FancyIVec},
+  writs::{PublicWrit, Writ, WritCursor, WritPage, WritQuery},
+};
+
+// what an anonymous writ query allows at most
+const MAX_ARCHIVE_PAGE: u64 = 50;
+
+const ARCHIVE_MONTHS_MIGRATION: &[u8] = b"migration:archive_month_counts";
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct ArchiveMonth {
+  pub month: u8,
+  pub count: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct ArchiveYear {
+  pub year: i32,
+  pub count: u64,
+  pub months: Vec<ArchiveMonth>, // newest first
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct ArchiveQuery {
+  pub kind: Option<String>,
+  pub amount: Option<u64>,
+  pub page: Option<u64>,
+  pub cursor: Option<String>,
+}
+
+impl Orchestrator {
+  // month keys are {kind}:{yyyymm}, fixed width, so the year and month are just slices
+  pub fn date_archive(&self, kind: &str) -> Vec<ArchiveYear> {
+    let prefix = format!("{}:", kind);
+    let mut counts: BTreeMap<(i32, u8), u64> = BTreeMap::new();
+
+    for (key, count) in self.archive_months.scan_prefix(prefix.as_bytes()).filter_map(|res| res.ok()) {
+      let date = match key.get(prefix.len()..).and_then(|d| std::str::from_utf8(d).ok()) {
+        Some(date) if date.len() == 6 => date,
+        _ => continue,
+      };
+      if let (Ok(year), Ok(month)) = (date[..4].parse::<i32>(), date[4..].parse::<u8>()) {
+        counts.insert((year, month), count.to_u64());
+      }
+    }
+
+    let mut years: Vec<ArchiveYear> = vec![];
+    for ((year, month), count) in counts.into_iter().rev() {
+      if years.last().map_or(true, |y| y.year != year) {
+        years.push(ArchiveYear { year, count: 0, months: vec![] });
+      }
+      let y = years.last_mut().unwrap();
+      y.count += count;
+      y.months.push(ArchiveMonth { month, count });
+    }
+    years
+  }
+
+  // drafts still sit in the date index, the month counts only cover what anyone can read
+  pub fn count_archive_month_in_transaction(
+    &self,
+    archive_months: &TransactionalTree,
+    old: Option<&Writ>,
+    new: Option<&Writ>,
+  ) -> ConflictableTransactionResult<(), ()> {
+    if let Some(old) = old.filter(|w| w.public) {
+      let key = old.archive_month_key();
+      let count = archive_months.get(key.as_bytes())?.map_or(0, |raw| raw.to_u64());
+      if count <= 1 {
+        archive_months.remove(key.as_bytes())?;
+      } else {
+        archive_months.insert(key.as_bytes(), IVec::from_u64(count - 1))?;
+      }
+    }
+
+    if let Some(new) = new.filter(|w| w.public) {
+      let key = new.archive_month_key();
+      let count = archive_months.get(key.as_bytes())?.map_or(0, |raw| raw.to_u64());
+      archive_months.insert(key.as_bytes(), IVec::from_u64(count + 1))?;
+    }
+    Ok(())
+  }
+}
+
+pub fn count_archive_months(db: &sled::Db, writs: &sled::Tree, archive_months: &sled::Tree) {
+  if db.contains_key(ARCHIVE_MONTHS_MIGRATION).unwrap_or(false) {
+    return;
+  }
+
+  let mut counts: BTreeMap<String, u64> = BTreeMap::new();
+  for res in writs.iter() {
+    let (_, raw) = res.expect("failed to read the writs tree while counting archive months");
+    let writ = Writ::try_from_slice(&raw).unwrap();
+    if writ.public {
+      *counts.entry(writ.archive_month_key()).or_insert(0) += 1;
+    }
+  }
+
+  archive_months.clear().unwrap();
+  for (key, count) in counts.iter() {
+    archive_months.insert(key.as_bytes(), IVec::from_u64(*count)).unwrap();
+  }
+
+  archive_months.flush().unwrap();
+  db.insert(ARCHIVE_MONTHS_MIGRATION, &unix_timestamp().to_be_bytes()).unwrap();
+  if counts.len() > 0 {
+    println!("counted public writs for {} archive months", counts.len());
+  }
+}
+
+#[get("/archive")]
+pub async fn date_archive(query: web::Query<ArchiveQuery>) -> HttpResponse {
+  let kind = query.kind.as_deref().unwrap_or("post");
+  if ORC.writ_kind(kind).is_none() {
+    return responses::NotFound("no such writ kind");
+  }
+  responses::Ok(ORC.date_archive(kind))
+}
+
+#[get("/archive/{year}/{month}")]
+pub async fn date_archive_month(
+  path: web::Path<(i32, u8)>,
+  query: web::Query<ArchiveQuery>,
+) -> HttpResponse {
+  let (year, month) = path.into_inner();
+  if year < 0 || year > 9999 || month == 0 || month > 12 {
+    return responses::BadRequest("that's not a real month");
+  }
+
+  let kind = query.kind.clone().unwrap_or_else(|| "post".to_string());
+  if ORC.writ_kind(&kind).is_none() {
+    return responses::NotFound("no such writ kind");
+  }
+  if query.amount.map_or(false, |amount| amount > MAX_ARCHIVE_PAGE) {
+    return responses::BadRequest("that's more writs than a page holds");
+  }
+  if query.cursor.as_deref().map_or(false, |c| !c.is_empty() && WritCursor::decode(c).is_none()) {
+    return responses::BadRequest("malformed cursor");
+  }
+
+  let wq = WritQuery {
+    kind,
+    year: Some(year),
+    month: Some(month),
+    amount: query.amount,
+    page: query.page.unwrap_or(0),
+    cursor: query.cursor.clone(),
+    with_content: Some(false),
+    ..Default::default()
+  };
+
+  // the input's been checked, so nothing coming back just means the month's empty from here on
+  match ORC.public_writ_query_page(wq, None) {
+    Some(page) => responses::Ok(page),
+    None => responses::Ok(WritPage::<PublicWrit> { writs: vec![], next_cursor: None, truncated: false }),
+  }
+}
@@ -149,6 +149,7 @@ pub fn start_system() -> thread::JoinHandle<()> {
     thread::spawn(|| {
         loop {
             clean_up_all();
+            ORC.publish_due_writs();
             thread::sleep(std::time::Duration::from_secs(1));
         }
     })
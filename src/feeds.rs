@@ -0,0 +1,227 @@
+use actix_web::{get, http::StatusCode, web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::CONF;
+
+use crate::{
+    caching::{add_validators, etag_of, is_fresh},
+    orchestrator::ORC,
+    tags::TagMode,
+    utils::datetime_from_unix_timestamp,
+    writs::{PublicWrit, WritID, WritQuery},
+};
+
+const FEED_TITLE: &str = "Kurshok";
+const FEED_DESCRIPTION: &str = "For the brainsick ones";
+const FEED_CACHE_CONTROL: &str = "public, max-age=300";
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct FeedQuery {
+    pub kind: Option<String>,
+    pub tags: Option<String>,
+    pub omit_tags: Option<String>,
+    pub tag_mode: Option<TagMode>,
+    pub author: Option<String>,
+    pub amount: Option<u64>,
+}
+
+impl FeedQuery {
+    fn to_writ_query(&self) -> WritQuery {
+        let mut query = WritQuery::default();
+        if let Some(kind) = &self.kind {
+            query.kind = kind.clone();
+        }
+        query.tags = self.tags.as_ref().map(|t| split_list(t));
+        query.omit_tags = self.omit_tags.as_ref().map(|t| split_list(t));
+        query.tag_mode = self.tag_mode;
+        query.author_handle = self.author.clone();
+        query.public = Some(true);
+        query.with_content = Some(true);
+        query.amount = Some(self.amount.unwrap_or(20).min(50));
+        query
+    }
+}
+
+fn split_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn writ_url(domain: &str, pw: &PublicWrit) -> String {
+    match WritID::from_str(&pw.id) {
+        Some(wid) => format!(
+            "https://{}/{}/{}:{}",
+            domain,
+            ORC.kind_url_prefix(&pw.kind),
+            wid.author,
+            wid.id
+        ),
+        None => format!("https://{}/", domain),
+    }
+}
+
+// writ ids never change, so they make for good stable guids
+fn writ_guid(domain: &str, pw: &PublicWrit) -> String {
+    format!("tag:{},2021:{}", domain, pw.id)
+}
+
+fn rfc2822(timestamp: i64) -> String {
+    datetime_from_unix_timestamp(timestamp).format("%a, %d %b %Y %H:%M:%S +0000")
+}
+
+fn rfc3339(timestamp: i64) -> String {
+    datetime_from_unix_timestamp(timestamp).format(time::Format::Rfc3339)
+}
+
+fn feed_writs(fq: &FeedQuery) -> Vec<PublicWrit> {
+    ORC.public_writ_query(fq.to_writ_query(), None).unwrap_or(vec![])
+}
+
+pub fn render_rss(writs: &[PublicWrit], self_url: &str) -> String {
+    let domain = CONF.read().domain.clone();
+    let mut xml = String::new();
+    xml.push_str(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    xml.push_str(r#"<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom"><channel>"#);
+    xml.push_str(&format!("<title>{}</title>", FEED_TITLE));
+    xml.push_str(&format!("<link>https://{}/</link>", domain));
+    xml.push_str(&format!("<description>{}</description>", FEED_DESCRIPTION));
+    xml.push_str(&format!(
+        r#"<atom:link href="{}" rel="self" type="application/rss+xml"/>"#,
+        escape_xml(self_url)
+    ));
+    if let Some(latest) = writs.first() {
+        xml.push_str(&format!("<lastBuildDate>{}</lastBuildDate>", rfc2822(latest.posted)));
+    }
+
+    for pw in writs {
+        xml.push_str("<item>");
+        xml.push_str(&format!("<title>{}</title>", escape_xml(&pw.title)));
+        xml.push_str(&format!("<link>{}</link>", writ_url(&domain, pw)));
+        xml.push_str(&format!(
+            r#"<guid isPermaLink="false">{}</guid>"#,
+            writ_guid(&domain, pw)
+        ));
+        xml.push_str(&format!("<pubDate>{}</pubDate>", rfc2822(pw.posted)));
+        xml.push_str(&format!(
+            "<author>noreply@{} ({})</author>",
+            domain,
+            escape_xml(&pw.author_name)
+        ));
+        for tag in pw.tags.iter() {
+            xml.push_str(&format!("<category>{}</category>", escape_xml(tag)));
+        }
+        if let Some(content) = &pw.content {
+            xml.push_str(&format!("<description>{}</description>", escape_xml(content)));
+        }
+        xml.push_str("</item>");
+    }
+
+    xml.push_str("</channel></rss>");
+    xml
+}
+
+pub fn render_atom(writs: &[PublicWrit], self_url: &str) -> String {
+    let domain = CONF.read().domain.clone();
+    let mut xml = String::new();
+    xml.push_str(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    xml.push_str(r#"<feed xmlns="http://www.w3.org/2005/Atom">"#);
+    xml.push_str(&format!("<title>{}</title>", FEED_TITLE));
+    xml.push_str(&format!("<subtitle>{}</subtitle>", FEED_DESCRIPTION));
+    xml.push_str(&format!("<id>https://{}/</id>", domain));
+    xml.push_str(&format!(r#"<link href="https://{}/"/>"#, domain));
+    xml.push_str(&format!(r#"<link href="{}" rel="self"/>"#, escape_xml(self_url)));
+    xml.push_str(&format!(
+        "<updated>{}</updated>",
+        rfc3339(writs.first().map_or(0, |pw| pw.posted))
+    ));
+
+    for pw in writs {
+        xml.push_str("<entry>");
+        xml.push_str(&format!("<title>{}</title>", escape_xml(&pw.title)));
+        xml.push_str(&format!(r#"<link href="{}"/>"#, writ_url(&domain, pw)));
+        xml.push_str(&format!("<id>{}</id>", writ_guid(&domain, pw)));
+        xml.push_str(&format!("<published>{}</published>", rfc3339(pw.posted)));
+        xml.push_str(&format!("<updated>{}</updated>", rfc3339(pw.posted)));
+        xml.push_str(&format!(
+            "<author><name>{}</name></author>",
+            escape_xml(&pw.author_name)
+        ));
+        for tag in pw.tags.iter() {
+            xml.push_str(&format!(r#"<category term="{}"/>"#, escape_xml(tag)));
+        }
+        if let Some(content) = &pw.content {
+            xml.push_str(&format!(r#"<content type="html">{}</content>"#, escape_xml(content)));
+        }
+        xml.push_str("</entry>");
+    }
+
+    xml.push_str("</feed>");
+    xml
+}
+
+pub fn render_json_feed(writs: &[PublicWrit], self_url: &str) -> String {
+    let domain = CONF.read().domain.clone();
+    let items: Vec<serde_json::Value> = writs.iter()
+        .map(|pw| json!({
+            "id": writ_guid(&domain, pw),
+            "url": writ_url(&domain, pw),
+            "title": pw.title,
+            "content_html": pw.content,
+            "date_published": rfc3339(pw.posted),
+            "tags": pw.tags,
+            "authors": [{ "name": pw.author_name }],
+        }))
+        .collect();
+
+    json!({
+        "version": "https://jsonfeed.org/version/1.1",
+        "title": FEED_TITLE,
+        "description": FEED_DESCRIPTION,
+        "home_page_url": format!("https://{}/", domain),
+        "feed_url": self_url,
+        "items": items,
+    }).to_string()
+}
+
+fn feed_response(req: &HttpRequest, body: String, content_type: &str) -> HttpResponse {
+    let etag = etag_of(body.as_bytes());
+    let mut res = HttpResponse::Ok();
+    let res = add_validators(&mut res, &etag, None, FEED_CACHE_CONTROL);
+    if is_fresh(req, &etag) {
+        return res.status(StatusCode::NOT_MODIFIED).finish().into_body();
+    }
+    res.content_type(content_type).body(body)
+}
+
+fn self_url(req: &HttpRequest) -> String {
+    format!("https://{}{}", CONF.read().domain, req.uri())
+}
+
+#[get("/feed.xml")]
+pub async fn rss_feed(req: HttpRequest, fq: web::Query<FeedQuery>) -> HttpResponse {
+    let writs = feed_writs(&fq);
+    feed_response(&req, render_rss(&writs, &self_url(&req)), "application/rss+xml; charset=utf-8")
+}
+
+#[get("/atom.xml")]
+pub async fn atom_feed(req: HttpRequest, fq: web::Query<FeedQuery>) -> HttpResponse {
+    let writs = feed_writs(&fq);
+    feed_response(&req, render_atom(&writs, &self_url(&req)), "application/atom+xml; charset=utf-8")
+}
+
+#[get("/feed.json")]
+pub async fn json_feed(req: HttpRequest, fq: web::Query<FeedQuery>) -> HttpResponse {
+    let writs = feed_writs(&fq);
+    feed_response(&req, render_json_feed(&writs, &self_url(&req)), "application/feed+json; charset=utf-8")
+}
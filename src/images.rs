@@ -0,0 +1,251 @@
+use actix_files::NamedFile;
+use actix_web::{get, http::header, http::HeaderValue, web, HttpRequest, HttpResponse};
+use image::{imageops::FilterType, DynamicImage, ImageOutputFormat};
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+
+use std::lazy::SyncLazy;
+
+use crate::{
+  media::{is_media_hash, media_dir, media_path},
+  orchestrator::ORC,
+  responses,
+  utils::to_hex,
+};
+
+static MEDIA_IMG_REGEX: SyncLazy<Regex> = SyncLazy::new(|| {
+  Regex::new(r#"<img src="/file/([0-9a-f]{16,128})""#).unwrap()
+});
+
+// requested widths get snapped up to one of these so the disk cache can't be filled with every width under the sun
+pub const DERIVATIVE_WIDTHS: &[u32] = &[320, 640, 960, 1280, 1920];
+// what a writ's images are laid out at, at most, so browsers don't pick the biggest one on wide screens
+const CONTENT_IMG_SIZES: &str = "(max-width: 960px) 100vw, 960px";
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DerivedFormat {
+  WebP,
+  Jpeg,
+  Png,
+}
+
+impl DerivedFormat {
+  // anything that can't be webp keeps looking like its source, gifs lose their animation either way
+  pub fn pick(source_mime: &str, accepts_webp: bool) -> Self {
+    if accepts_webp {
+      Self::WebP
+    } else if source_mime == "image/png" || source_mime == "image/gif" {
+      Self::Png
+    } else {
+      Self::Jpeg
+    }
+  }
+
+  pub fn ext(&self) -> &'static str {
+    match self {
+      Self::WebP => "webp",
+      Self::Jpeg => "jpg",
+      Self::Png => "png",
+    }
+  }
+
+  pub fn mime(&self) -> &'static str {
+    match self {
+      Self::WebP => "image/webp",
+      Self::Jpeg => "image/jpeg",
+      Self::Png => "image/png",
+    }
+  }
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct ImageQuery {
+  pub w: Option<u32>,
+}
+
+pub fn is_derivable(mime: &str) -> bool {
+  matches!(mime, "image/jpeg" | "image/png" | "image/webp" | "image/gif")
+}
+
+pub fn snap_width(width: u32) -> u32 {
+  DERIVATIVE_WIDTHS
+    .iter()
+    .copied()
+    .find(|dw| *dw >= width)
+    .unwrap_or(DERIVATIVE_WIDTHS[DERIVATIVE_WIDTHS.len() - 1])
+}
+
+pub fn derived_dir() -> String {
+  format!("{}derived/", media_dir())
+}
+
+pub fn derived_path(source_hash: &str, width: u32, format: DerivedFormat) -> String {
+  format!("{}{}-{}.{}", derived_dir(), source_hash, width, format.ext())
+}
+
+// never scales up, a small source just gets re-encoded
+pub fn derive_image(source: &[u8], width: u32, format: DerivedFormat) -> Option<Vec<u8>> {
+  let mut img = image::load_from_memory(source).ok()?;
+  if img.width() > width {
+    img = img.resize(width, u32::MAX, FilterType::Lanczos3);
+  }
+
+  let mut out: Vec<u8> = vec![];
+  match format {
+    DerivedFormat::WebP => {
+      let rgba = DynamicImage::ImageRgba8(img.to_rgba8());
+      out.extend_from_slice(&webp::Encoder::from_image(&rgba).encode(80.0));
+    },
+    DerivedFormat::Jpeg => img.write_to(&mut out, ImageOutputFormat::Jpeg(85)).ok()?,
+    DerivedFormat::Png => img.write_to(&mut out, ImageOutputFormat::Png).ok()?,
+  }
+  Some(out)
+}
+
+// adds a srcset to every image in rendered markdown that points at the media store
+pub fn add_media_srcsets(html: String) -> String {
+  if !html.contains("<img src=\"/file/") {
+    return html;
+  }
+
+  MEDIA_IMG_REGEX
+    .replace_all(&html, |caps: &Captures| {
+      let hash = &caps[1];
+      match media_srcset(hash) {
+        Some(srcset) => format!(
+          r#"<img src="/file/{}" srcset="{}" sizes="{}""#,
+          hash, srcset, CONTENT_IMG_SIZES
+        ),
+        None => caps[0].to_string(),
+      }
+    })
+    .into_owned()
+}
+
+pub fn media_srcset(hash: &str) -> Option<String> {
+  let meta = ORC.media_meta(hash)?;
+  if !is_derivable(&meta.mime) {
+    return None;
+  }
+  let width = meta.width?;
+
+  let mut entries: Vec<String> = DERIVATIVE_WIDTHS
+    .iter()
+    .filter(|dw| **dw < width)
+    .map(|dw| format!("/img/{}?w={} {}w", hash, dw, dw))
+    .collect();
+  if entries.is_empty() {
+    return None;
+  }
+  entries.push(format!("/file/{} {}w", hash, width));
+  Some(entries.join(", "))
+}
+
+fn accepts_webp(req: &HttpRequest) -> bool {
+  req.headers()
+    .get(header::ACCEPT)
+    .and_then(|accept| accept.to_str().ok())
+    .map_or(false, |accept| accept.contains("image/webp"))
+}
+
+async fn serve_derived(
+  req: &HttpRequest,
+  source_hash: String,
+  source_path: String,
+  format: DerivedFormat,
+  width: u32,
+  cache_control: &'static str,
+) -> HttpResponse {
+  let path = derived_path(&source_hash, width, format);
+
+  if let Ok(file) = NamedFile::open(&path) {
+    let mut res = file
+      .set_content_type(format.mime().parse().unwrap())
+      .into_response(req);
+    let headers = res.headers_mut();
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static(cache_control));
+    headers.insert(header::VARY, HeaderValue::from_static("Accept"));
+    return res;
+  }
+
+  // resizing is heavy enough that it shouldn't hold up a worker thread
+  let derived = tokio::task::spawn_blocking(move || {
+    let source = std::fs::read(&source_path).ok()?;
+    let bytes = derive_image(&source, width, format)?;
+    if let Err(e) = std::fs::create_dir_all(derived_dir()).and_then(|_| std::fs::write(&path, &bytes)) {
+      if ORC.dev_mode {
+        println!("couldn't cache a derived image at {} - {}", path, e);
+      }
+    }
+    Some(bytes)
+  }).await;
+
+  match derived {
+    Ok(Some(bytes)) => HttpResponse::Ok()
+      .content_type(format.mime())
+      .append_header((header::CACHE_CONTROL, cache_control))
+      .append_header((header::VARY, "Accept"))
+      .body(bytes),
+    _ => responses::InternalServerError("couldn't make that image"),
+  }
+}
+
+#[get("/img/{hash}")]
+pub async fn media_image(
+  req: HttpRequest,
+  hash: web::Path<String>,
+  query: web::Query<ImageQuery>,
+) -> HttpResponse {
+  if !is_media_hash(&hash) {
+    return responses::NotFound("no such image");
+  }
+
+  let meta = match ORC.media_meta(&hash) {
+    Some(meta) if meta.is_image() => meta,
+    _ => return responses::NotFound("no such image"),
+  };
+
+  // svgs and such don't need resizing, the original will do
+  if !is_derivable(&meta.mime) {
+    return HttpResponse::Found()
+      .append_header((header::LOCATION, meta.url()))
+      .finish()
+      .into_body();
+  }
+
+  let width = snap_width(query.w.unwrap_or(u32::MAX));
+  let format = DerivedFormat::pick(&meta.mime, accepts_webp(&req));
+  let source_path = media_path(&meta.hash);
+  serve_derived(&req, meta.hash, source_path, format, width, "public, max-age=31536000, immutable").await
+}
+
+// the site's own images in ./assets/media, they're keyed by content too so edits show up straight away
+#[get("/img/media/{file}")]
+pub async fn local_image(
+  req: HttpRequest,
+  file: web::Path<String>,
+  query: web::Query<ImageQuery>,
+) -> HttpResponse {
+  let name = file.into_inner();
+  if name.starts_with('.') || name.contains('/') || name.contains('\\') {
+    return responses::NotFound("no such image");
+  }
+
+  let mime = match name.rsplit('.').next().map(|ext| ext.to_lowercase()).as_deref() {
+    Some("jpg") | Some("jpeg") => "image/jpeg",
+    Some("png") => "image/png",
+    Some("webp") => "image/webp",
+    Some("gif") => "image/gif",
+    _ => return responses::NotFound("no such image"),
+  };
+
+  let source_path = format!("./assets/media/{}", name);
+  let source_hash = match std::fs::read(&source_path) {
+    Ok(bytes) => to_hex(&ORC.hash(&bytes)),
+    Err(_) => return responses::NotFound("no such image"),
+  };
+
+  let width = snap_width(query.w.unwrap_or(u32::MAX));
+  let format = DerivedFormat::pick(mime, accepts_webp(&req));
+  serve_derived(&req, source_hash, source_path, format, width, "public, max-age=86400").await
+}
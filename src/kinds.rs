@@ -0,0 +1,264 @@
+use actix_web::{delete, dev::RequestHead, get, put, web, HttpRequest, HttpResponse};
+use borsh::{BorshDeserialize, BorshSerialize};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use sled::Tree;
+
+use std::{collections::HashSet, lazy::SyncLazy};
+
+use crate::orchestrator::{Orchestrator, ORC};
+use crate::responses;
+use crate::sitemap::invalidate_sitemap;
+
+// top level paths the server already answers for, a kind taking one would shadow them,
+// the pages rendered straight from ./templates are on top of these
+const RESERVED_URL_PREFIXES: &[&str] = &[
+  "admin", "archive", "assets", "auth", "comment", "comments", "css", "edit-comment",
+  "editable-writs", "expire-data", "feed", "file", "img", "js", "kind", "kinds", "media",
+  "post-content", "reactions", "reload-templates", "remote-http", "renew-cert", "robots",
+  "series", "sitemap", "stats", "tags", "upload", "user", "writ", "writ-raw-content", "writs", "ws",
+];
+
+// url prefixes of every registered kind, checked on each request that might be a writ
+static KIND_PREFIXES: SyncLazy<RwLock<Option<HashSet<String>>>> = SyncLazy::new(|| RwLock::new(None));
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct WritKind {
+  pub kind: String, // always 4 bytes, it's baked into every writ id
+  pub display_name: String,
+  pub template: String,
+  pub markdown_required: bool,
+  pub commentable_by_default: bool,
+  pub author_attrs: Vec<String>, // any of these lets a user author the kind, empty means any writer
+  pub url_prefix: String,
+}
+
+impl WritKind {
+  // what every instance had before kinds could be registered
+  pub fn default_post() -> Self {
+    Self {
+      kind: "post".to_string(),
+      display_name: "Post".to_string(),
+      template: "post.html".to_string(),
+      markdown_required: false,
+      commentable_by_default: true,
+      author_attrs: vec![],
+      url_prefix: "post".to_string(),
+    }
+  }
+
+  pub fn is_valid(&self) -> bool {
+    self.kind.len() == 4
+      && !self.url_prefix.is_empty()
+      && self.url_prefix.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+      && !self.shadows_a_page()
+      && !self.template.is_empty()
+  }
+
+  // a kind may share its name with its own template, every other page the site serves is off limits
+  pub fn shadows_a_page(&self) -> bool {
+    let prefix = self.url_prefix.to_lowercase();
+    RESERVED_URL_PREFIXES.contains(&prefix.as_str())
+      || (template_pages().contains(&prefix) && self.template.to_lowercase() != format!("{}.html", prefix))
+  }
+
+  pub fn slug_path(&self, slug: &str) -> String {
+    format!("/{}/{}", self.url_prefix, slug)
+  }
+
+  pub fn id_path(&self, author_id: u64, id: u64) -> String {
+    format!("/{}/{}:{}", self.url_prefix, author_id, id)
+  }
+}
+
+// the pages serve_files_and_templates renders for /{name}, read fresh since templates can be reloaded
+fn template_pages() -> HashSet<String> {
+  match std::fs::read_dir("./templates") {
+    Ok(dir) => dir
+      .filter_map(|entry| entry.ok())
+      .filter_map(|entry| entry.file_name().to_str()?.strip_suffix(".html").map(|name| name.to_lowercase()))
+      .collect(),
+    Err(_) => HashSet::new(),
+  }
+}
+
+// config kinds win over whatever the db had, and there's always a post kind,
+// a kind that would shadow one of the site's pages stops startup instead of quietly taking it over
+pub fn seed_kinds(kinds: &Tree, configured: &[WritKind]) {
+  let stored = kinds.iter()
+    .values()
+    .filter_map(|res| res.ok())
+    .map(|raw| WritKind::try_from_slice(&raw).unwrap())
+    .filter(|wk| !configured.iter().any(|c| c.kind == wk.kind));
+  for kind in configured.iter().cloned().chain(stored) {
+    if kind.shadows_a_page() {
+      panic!(
+        "writ kind {:?} takes the url prefix {:?}, which the site already serves a page under, refusing to start",
+        kind.kind, kind.url_prefix
+      );
+    }
+  }
+
+  for kind in configured.iter() {
+    if !kind.is_valid() {
+      println!("skipping misconfigured writ kind {:?}", kind.kind);
+      continue;
+    }
+    kinds.insert(kind.kind.as_bytes(), kind.try_to_vec().unwrap()).unwrap();
+  }
+
+  if !kinds.contains_key(b"post").unwrap() {
+    let post = WritKind::default_post();
+    kinds.insert(post.kind.as_bytes(), post.try_to_vec().unwrap()).unwrap();
+  }
+}
+
+impl Orchestrator {
+  pub fn writ_kind(&self, kind: &str) -> Option<WritKind> {
+    match self.kinds.get(kind.as_bytes()) {
+      Ok(Some(raw)) => Some(WritKind::try_from_slice(&raw).unwrap()),
+      _ => None,
+    }
+  }
+
+  pub fn writ_kind_by_prefix(&self, url_prefix: &str) -> Option<WritKind> {
+    self.writ_kinds().into_iter().find(|wk| wk.url_prefix == url_prefix)
+  }
+
+  pub fn writ_kinds(&self) -> Vec<WritKind> {
+    self.kinds
+      .iter()
+      .values()
+      .filter_map(|res| res.ok())
+      .map(|raw| WritKind::try_from_slice(&raw).unwrap())
+      .collect()
+  }
+
+  pub fn can_author_kind(&self, usr_id: u64, wk: &WritKind) -> bool {
+    if wk.author_attrs.is_empty() {
+      return true;
+    }
+    let attrs: Vec<&str> = wk.author_attrs.iter().map(|a| a.as_str()).collect();
+    self.user_has_some_attrs(usr_id, &attrs).unwrap_or(false) || self.is_admin(usr_id)
+  }
+
+  // where a writ of some kind lives on the site, unregistered kinds just use their name
+  pub fn kind_url_prefix(&self, kind: &str) -> String {
+    self.writ_kind(kind).map_or(kind.to_string(), |wk| wk.url_prefix)
+  }
+
+  pub fn register_writ_kind(&self, wk: &WritKind) -> bool {
+    if !wk.is_valid() {
+      return false;
+    }
+    let prefix_taken = self.writ_kinds()
+      .iter()
+      .any(|other| other.kind != wk.kind && other.url_prefix == wk.url_prefix);
+    if prefix_taken || self.kinds.insert(wk.kind.as_bytes(), wk.try_to_vec().unwrap()).is_err() {
+      return false;
+    }
+    // writ urls hang off the kind's prefix
+    forget_kind_prefixes();
+    invalidate_sitemap();
+    true
+  }
+
+  pub fn is_kind_prefix(&self, url_prefix: &str) -> bool {
+    if let Some(prefixes) = KIND_PREFIXES.read().as_ref() {
+      return prefixes.contains(url_prefix);
+    }
+
+    // filled in under the write lock so a kind registered meanwhile can't get lost
+    KIND_PREFIXES.write()
+      .get_or_insert_with(|| self.writ_kinds().into_iter().map(|wk| wk.url_prefix).collect())
+      .contains(url_prefix)
+  }
+}
+
+pub fn forget_kind_prefixes() {
+  *KIND_PREFIXES.write() = None;
+}
+
+// only lets the generic writ routes take paths that start with a registered prefix,
+// everything else falls through to the file and template server
+pub fn is_registered_kind_path(head: &RequestHead) -> bool {
+  head.uri.path()
+    .trim_start_matches('/')
+    .split('/')
+    .next()
+    .map_or(false, |prefix| ORC.is_kind_prefix(prefix))
+}
+
+#[get("/kinds")]
+pub async fn list_writ_kinds() -> HttpResponse {
+  responses::Ok(ORC.writ_kinds())
+}
+
+#[put("/kind")]
+pub async fn register_writ_kind(
+  req: HttpRequest,
+  wk: web::Json<WritKind>,
+) -> HttpResponse {
+  if ORC.admin_by_session(&req).is_none() {
+    return responses::Forbidden("only admins may register writ kinds");
+  }
+
+  if ORC.register_writ_kind(&wk) {
+    return responses::Ok(wk.into_inner());
+  }
+  responses::BadRequest("kinds need a 4 byte name, a template and a url prefix that neither another kind nor the site itself uses")
+}
+
+#[delete("/kind/{kind}")]
+pub async fn remove_writ_kind(
+  req: HttpRequest,
+  kind: web::Path<String>,
+) -> HttpResponse {
+  if ORC.admin_by_session(&req).is_none() {
+    return responses::Forbidden("only admins may remove writ kinds");
+  }
+
+  // existing writs of the kind stay put, they just can't be rendered or added to
+  match ORC.kinds.remove(kind.as_bytes()) {
+    Ok(Some(_)) => {
+      forget_kind_prefixes();
+      invalidate_sitemap();
+      responses::Accepted("writ kind removed")
+    },
+    Ok(None) => responses::NotFound("no such writ kind"),
+    Err(_) => responses::InternalServerError("couldn't remove the writ kind"),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn kind_under(url_prefix: &str, template: &str) -> WritKind {
+    WritKind {
+      kind: "book".to_string(),
+      display_name: "Book".to_string(),
+      template: template.to_string(),
+      markdown_required: false,
+      commentable_by_default: true,
+      author_attrs: vec![],
+      url_prefix: url_prefix.to_string(),
+    }
+  }
+
+  #[test]
+  fn kinds_cannot_shadow_template_pages() {
+    // books.html is served at /books
+    assert!(kind_under("books", "post.html").shadows_a_page());
+    assert!(kind_under("Privacy-Policy", "post.html").shadows_a_page());
+    assert!(kind_under("post", "books.html").shadows_a_page());
+    assert!(kind_under("admin", "admin.html").shadows_a_page());
+  }
+
+  #[test]
+  fn kinds_may_live_under_their_own_template() {
+    assert!(!WritKind::default_post().shadows_a_page());
+    assert!(!kind_under("book", "post.html").shadows_a_page());
+    assert!(WritKind::default_post().is_valid());
+  }
+}
@@ -8,14 +8,36 @@ use mimalloc::MiMalloc;
 static GLOBAL: MiMalloc = MiMalloc;
 
 mod admin_functions;
+mod analytics;
+mod archive;
+mod caching;
+mod collaborators;
 mod auth;
 mod email;
 mod expirable_data;
+mod feeds;
+mod images;
+mod kinds;
+mod media;
 mod comments;
+mod date_archive;
 mod orchestrator;
 mod posts;
+mod post_meta;
+mod rankings;
 mod ratelimiter;
+mod reactions;
+mod reading;
+mod related;
 mod responses;
+mod revisions;
+mod sanitizer;
+mod search;
+mod series;
+mod share_links;
+mod sitemap;
+mod slugs;
+mod tags;
 mod utils;
 mod writs;
 mod websockets;
@@ -159,8 +181,47 @@ fn start_server() -> actix_web::dev::Server {
             .service(writs::upvote_writ)
             .service(writs::unvote_writ)
             .service(writs::downvote_writ)
+            .service(reactions::react_writ)
+            .service(reactions::unreact_writ)
+            .service(reactions::list_reactions)
             .service(writs::post_content)
             .service(writs::writ_raw_content)
+            .service(archive::export_writs)
+            .service(archive::import_writs)
+            .service(revisions::list_writ_revisions)
+            .service(revisions::get_writ_revision)
+            .service(revisions::diff_writ_revisions)
+            .service(revisions::restore_writ_revision)
+            .service(collaborators::list_writ_collaborators)
+            .service(collaborators::set_writ_collaborator)
+            .service(collaborators::remove_writ_collaborator)
+            .service(analytics::writ_stats)
+            .service(analytics::author_stats)
+            .service(analytics::site_stats)
+            .service(related::related_writs)
+            .service(date_archive::date_archive)
+            .service(date_archive::date_archive_month)
+            .service(share_links::create_share_link)
+            .service(share_links::list_share_links)
+            .service(share_links::revoke_share_link)
+            .service(tags::tag_directory)
+            .service(tags::autocomplete_tags)
+            .service(tags::related_tags)
+            .service(kinds::list_writ_kinds)
+            .service(kinds::register_writ_kind)
+            .service(kinds::remove_writ_kind)
+            .service(series::list_author_series)
+            .service(series::get_series)
+            .service(series::create_series)
+            .service(series::reorder_series)
+            .service(series::remove_writ_from_series)
+            .service(series::delete_series)
+            .service(media::upload_media)
+            .service(media::media_metadata)
+            .service(media::serve_media)
+            .service(media::delete_media)
+            .service(images::local_image)
+            .service(images::media_image)
             .service(comments::post_comment_query)
             .service(comments::edit_comment_request)
             .service(comments::fetch_comment_raw_content)
@@ -169,12 +230,20 @@ fn start_server() -> actix_web::dev::Server {
             .service(comments::upvote_comment)
             .service(comments::unvote_comment)
             .service(comments::downvote_comment)
+            .service(reactions::react_comment)
+            .service(reactions::unreact_comment)
             .service(posts::render_post)
             .service(posts::render_post_by_slug)
+            .service(feeds::rss_feed)
+            .service(feeds::atom_feed)
+            .service(feeds::json_feed)
             .service(web::resource("/ws").to(websockets::ws_conn_setup))
             .service(admin_functions::remote_http)
             .service(admin_functions::reload_templates_request)
             .service(admin_functions::expire_data_request)
+            .service(sitemap::sitemap)
+            .service(sitemap::sitemap_page)
+            .service(sitemap::robots_txt)
             .service(admin_panel)
             .service(serve_files_and_templates)
     })
@@ -196,6 +265,12 @@ pub struct Config {
     pub smtp_password: String,
     cert_path: String,
     privkey_path: String,
+    #[serde(default)]
+    pub kinds: Vec<kinds::WritKind>,
+    #[serde(default)]
+    pub sanitizer: sanitizer::SanitizerConfig,
+    #[serde(default = "reactions::default_reactions")]
+    pub reactions: Vec<reactions::Reaction>,
 }
 
 #[get("/")]
@@ -0,0 +1,373 @@
+use actix_files::NamedFile;
+use actix_multipart::Multipart;
+use actix_web::{
+  delete, get,
+  http::header::{self, ContentDisposition, DispositionParam, DispositionType},
+  http::HeaderValue,
+  post, web, HttpRequest, HttpResponse,
+};
+use borsh::{BorshDeserialize, BorshSerialize};
+use futures::{StreamExt, TryStreamExt};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sled::transaction::*;
+
+use std::{collections::BTreeSet, io::Cursor, lazy::SyncLazy};
+use time::Duration;
+
+use super::CONF;
+
+use crate::{
+  images::derived_dir,
+  orchestrator::{Orchestrator, ORC},
+  responses,
+  utils::{to_hex, unix_timestamp},
+};
+
+static MEDIA_REF_REGEX: SyncLazy<Regex> = SyncLazy::new(|| {
+  Regex::new(r"/file/([0-9a-f]{16,128})").unwrap()
+});
+
+const MAX_UPLOAD_SIZE: usize = 20 * 1024 * 1024;
+// raster images can't carry scripts, so when the bytes themselves say they're one of these
+// they're shown inline, anything else (svg and html included) only ever gets downloaded
+const INLINE_MIMES: &[&str] = &[
+  "image/png", "image/jpeg", "image/gif", "image/webp", "image/bmp", "image/avif", "image/x-icon",
+];
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct MediaMeta {
+  pub hash: String,
+  pub mime: String,
+  pub size: u64,
+  pub uploader: u64,
+  pub filename: Option<String>,
+  pub width: Option<u32>,
+  pub height: Option<u32>,
+  pub uploaded: i64,
+  pub writs: Vec<String>, // ids of the writs that link to it
+}
+
+impl MediaMeta {
+  pub fn url(&self) -> String {
+    format!("/file/{}", self.hash)
+  }
+
+  pub fn is_image(&self) -> bool {
+    self.mime.starts_with("image/")
+  }
+
+}
+
+// what the stored bytes actually are, never what the uploader said they were
+fn inline_mime(path: &str) -> Option<mime::Mime> {
+  let kind = infer::get_from_path(path).ok()??;
+  if !INLINE_MIMES.contains(&kind.mime_type()) {
+    return None;
+  }
+  kind.mime_type().parse().ok()
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct UploadedMedia {
+  pub url: String,
+  #[serde(flatten)]
+  pub meta: MediaMeta,
+}
+
+impl Orchestrator {
+  pub fn media_meta(&self, hash: &str) -> Option<MediaMeta> {
+    match self.media.get(hash.as_bytes()) {
+      Ok(Some(raw)) => Some(MediaMeta::try_from_slice(&raw).unwrap()),
+      _ => None,
+    }
+  }
+
+  // same bytes, same hash, so a re-upload just hands back what's already there
+  pub fn store_media(&self, uploader: u64, filename: Option<String>, declared_mime: &str, bytes: Vec<u8>) -> Option<MediaMeta> {
+    let hash = to_hex(&self.hash(&bytes));
+    if let Some(existing) = self.media_meta(&hash) {
+      return Some(existing);
+    }
+
+    let mime = match infer::get(&bytes) {
+      Some(kind) => kind.mime_type().to_string(),
+      None if !declared_mime.is_empty() => declared_mime.to_string(),
+      None => "application/octet-stream".to_string(),
+    };
+
+    let dimensions = if mime.starts_with("image/") {
+      image::io::Reader::new(Cursor::new(&bytes))
+        .with_guessed_format()
+        .ok()
+        .and_then(|reader| reader.into_dimensions().ok())
+    } else {
+      None
+    };
+
+    let dir = media_dir();
+    if let Err(e) = std::fs::create_dir_all(&dir).and_then(|_| std::fs::write(media_path(&hash), &bytes)) {
+      if self.dev_mode {
+        println!("failed to write uploaded media to {} - {}", dir, e);
+      }
+      return None;
+    }
+
+    let meta = MediaMeta {
+      hash,
+      mime,
+      size: bytes.len() as u64,
+      uploader,
+      filename,
+      width: dimensions.map(|(w, _)| w),
+      height: dimensions.map(|(_, h)| h),
+      uploaded: unix_timestamp(),
+      writs: vec![],
+    };
+
+    match self.media.insert(meta.hash.as_bytes(), meta.try_to_vec().unwrap()) {
+      Ok(_) => Some(meta),
+      Err(_) => None,
+    }
+  }
+
+  // points a writ's media references at what its content links to now,
+  // hands back the hashes nothing links to anymore so their files can go once the transaction's through
+  pub fn relink_writ_media_in_transaction(
+    &self,
+    media: &TransactionalTree,
+    writ_media: &TransactionalTree,
+    writ_id: &str,
+    wid: &[u8],
+    referenced: &BTreeSet<String>,
+  ) -> ConflictableTransactionResult<Vec<String>, ()> {
+    let old: BTreeSet<String> = match writ_media.get(wid)? {
+      Some(raw) => BorshDeserialize::try_from_slice(&raw).unwrap(),
+      None => BTreeSet::new(),
+    };
+
+    let mut linked: BTreeSet<String> = BTreeSet::new();
+    for hash in referenced.iter() {
+      if let Some(raw) = media.get(hash.as_bytes())? {
+        let mut meta = MediaMeta::try_from_slice(&raw).unwrap();
+        if !meta.writs.iter().any(|id| id == writ_id) {
+          meta.writs.push(writ_id.to_string());
+          media.insert(hash.as_bytes(), meta.try_to_vec().unwrap())?;
+        }
+        linked.insert(hash.clone());
+      }
+    }
+
+    let mut orphans = vec![];
+    for hash in old.difference(&linked) {
+      if let Some(raw) = media.get(hash.as_bytes())? {
+        let mut meta = MediaMeta::try_from_slice(&raw).unwrap();
+        meta.writs.retain(|id| id != writ_id);
+        if meta.writs.is_empty() {
+          media.remove(hash.as_bytes())?;
+          orphans.push(hash.clone());
+        } else {
+          media.insert(hash.as_bytes(), meta.try_to_vec().unwrap())?;
+        }
+      }
+    }
+
+    if linked.is_empty() {
+      writ_media.remove(wid)?;
+    } else {
+      writ_media.insert(wid, linked.try_to_vec().unwrap())?;
+    }
+
+    Ok(orphans)
+  }
+
+  pub fn remove_media_files(&self, hashes: &[String]) {
+    for hash in hashes.iter() {
+      if let Err(e) = std::fs::remove_file(media_path(hash)) {
+        if self.dev_mode {
+          println!("couldn't remove media file {} - {}", hash, e);
+        }
+      }
+    }
+
+    // resized copies go along with their source
+    if let Ok(entries) = std::fs::read_dir(derived_dir()) {
+      for entry in entries.filter_map(|entry| entry.ok()) {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if hashes.iter().any(|hash| name.starts_with(&format!("{}-", hash))) {
+          let _ = std::fs::remove_file(entry.path());
+        }
+      }
+    }
+  }
+}
+
+pub fn media_references(raw_content: &str) -> BTreeSet<String> {
+  MEDIA_REF_REGEX
+    .captures_iter(raw_content)
+    .map(|cap| cap[1].to_string())
+    .collect()
+}
+
+pub fn media_dir() -> String {
+  format!("{}media/", CONF.read().db_location)
+}
+
+pub fn media_path(hash: &str) -> String {
+  format!("{}{}", media_dir(), hash)
+}
+
+pub fn is_media_hash(hash: &str) -> bool {
+  hash.len() >= 16 && hash.len() <= 128 && hash.chars().all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase())
+}
+
+#[post("/upload")]
+pub async fn upload_media(req: HttpRequest, mut payload: Multipart) -> HttpResponse {
+  let usr_id = match ORC.user_id_by_session(&req) {
+    Some(id) => id,
+    None => return responses::Forbidden("you have to be logged in to upload things"),
+  };
+
+  if !ORC.user_has_some_attrs(usr_id, &["writer", "admin"]).unwrap_or(false) {
+    return responses::Forbidden("only writers may upload media");
+  }
+
+  let hitter = format!("upld{}", usr_id);
+  if let Some(rl) = ORC.ratelimiter
+    .hit(hitter.as_bytes(), 30, Duration::minutes(10))
+  {
+    if rl.is_timing_out() {
+      return responses::TooManyRequests(format!(
+        "too many uploads, timeout has {} minutes left.",
+        rl.minutes_left()
+      ));
+    }
+  }
+
+  let mut uploaded: Vec<UploadedMedia> = vec![];
+
+  while let Ok(Some(mut field)) = payload.try_next().await {
+    let filename = field
+      .content_disposition()
+      .and_then(|cd| cd.get_filename().map(|name| name.to_string()));
+    let declared_mime = field.content_type().to_string();
+
+    let mut bytes: Vec<u8> = vec![];
+    while let Some(chunk) = field.next().await {
+      let chunk = match chunk {
+        Ok(chunk) => chunk,
+        Err(_) => return responses::BadRequest("the upload broke off halfway"),
+      };
+      if bytes.len() + chunk.len() > MAX_UPLOAD_SIZE {
+        return responses::BadRequest("files can't be bigger than 20MB");
+      }
+      bytes.extend_from_slice(&chunk);
+    }
+
+    if bytes.is_empty() {
+      continue;
+    }
+
+    match ORC.store_media(usr_id, filename, &declared_mime, bytes) {
+      Some(meta) => uploaded.push(UploadedMedia { url: meta.url(), meta }),
+      None => return responses::InternalServerError("couldn't store the upload"),
+    }
+  }
+
+  if uploaded.is_empty() {
+    return responses::BadRequest("there was nothing in the upload");
+  }
+  responses::Ok(uploaded)
+}
+
+#[get("/file/{hash}")]
+pub async fn serve_media(req: HttpRequest, hash: web::Path<String>) -> HttpResponse {
+  if !is_media_hash(&hash) {
+    return responses::NotFound("no such file");
+  }
+
+  let meta = match ORC.media_meta(&hash) {
+    Some(meta) => meta,
+    None => return responses::NotFound("no such file"),
+  };
+
+  let path = media_path(&meta.hash);
+  let file = match NamedFile::open(&path) {
+    Ok(file) => file,
+    Err(_) => return responses::NotFound("no such file"),
+  };
+
+  let file = match inline_mime(&path) {
+    Some(mime) => file
+      .set_content_type(mime)
+      .set_content_disposition(ContentDisposition {
+        disposition: DispositionType::Inline,
+        parameters: vec![],
+      }),
+    None => file
+      .set_content_type(mime::APPLICATION_OCTET_STREAM)
+      .set_content_disposition(ContentDisposition {
+        disposition: DispositionType::Attachment,
+        parameters: vec![DispositionParam::Filename(
+          meta.filename.clone().unwrap_or_else(|| meta.hash.clone())
+        )],
+      }),
+  };
+
+  let mut res = file.into_response(&req);
+  let headers = res.headers_mut();
+  // content addressed, so whatever's at this url never changes
+  headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("public, max-age=31536000, immutable"));
+  headers.insert(header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+  res
+}
+
+// it lists every writ linking to the file, drafts and private ones too, so it's the uploader's alone
+#[get("/file/{hash}/meta")]
+pub async fn media_metadata(req: HttpRequest, hash: web::Path<String>) -> HttpResponse {
+  let usr_id = match ORC.user_id_by_session(&req) {
+    Some(id) => id,
+    None => return responses::Forbidden("you have to be logged in to see a file's details"),
+  };
+
+  let meta = match ORC.media_meta(&hash) {
+    Some(meta) => meta,
+    None => return responses::NotFound("no such file"),
+  };
+
+  if meta.uploader != usr_id && !ORC.is_admin(usr_id) {
+    return responses::Forbidden("only the uploader may see a file's details");
+  }
+
+  responses::Ok(meta)
+}
+
+#[delete("/file/{hash}")]
+pub async fn delete_media(req: HttpRequest, hash: web::Path<String>) -> HttpResponse {
+  let usr_id = match ORC.user_id_by_session(&req) {
+    Some(id) => id,
+    None => return responses::Forbidden("you have to be logged in to remove files"),
+  };
+
+  let meta = match ORC.media_meta(&hash) {
+    Some(meta) => meta,
+    None => return responses::NotFound("no such file"),
+  };
+
+  if meta.uploader != usr_id && !ORC.is_admin(usr_id) {
+    return responses::Forbidden("only the uploader may remove a file");
+  }
+
+  if !meta.writs.is_empty() {
+    return responses::BadRequest("writs still link to that file, unlink it from them first");
+  }
+
+  match ORC.media.remove(meta.hash.as_bytes()) {
+    Ok(_) => {
+      ORC.remove_media_files(&[meta.hash]);
+      responses::Accepted("file removed")
+    },
+    Err(_) => responses::InternalServerError("couldn't remove the file"),
+  }
+}
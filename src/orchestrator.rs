@@ -50,13 +50,46 @@ pub struct Orchestrator {
   pub kinds: Tree,
   pub titles: Tree, // title: writ_id
   pub slugs: Tree,
-  pub dates: Tree, // {kind}:yyyy/mm/dd
+  pub dates: Tree, // {kind}:{yyyymmddhh}:{id}: writ_id
+  pub archive_months: Tree, // {kind}:{yyyymm}: public writ count
 
   pub votes: Tree,       // writ_id: count
   pub writ_voters: Tree, // {user_id}:{writ_id} = {up_or_down, when}
 
   pub tags_index: Tree,
   pub tag_counter: Tree,
+  pub public_tags: Tree, // writ_id: [tag], only for public writs
+  pub public_tag_counter: Tree, // {tag} and {kind}:{tag}: how many public writs use it
+
+  pub writ_revisions: Tree, // {writ_id}{version}: WritRevision
+  pub scheduled_writs: Tree, // {publish_at}{writ_id}: writ_id
+
+  pub search_index: Tree, // {term}:{writ_id}: weight
+  pub search_terms: Tree, // writ_id: [term]
+
+  pub writ_ranks: Tree, // writ_id: WritRanks
+  pub writ_rankings: Tree, // {sort}{kind}{score}{writ_id}: writ_id
+
+  pub retired_slugs: Tree, // {kind}:{slug}: writ_id
+  pub slug_history: Tree, // writ_id: [RetiredSlug]
+
+  pub series: Tree, // {author_id}{series_id}: Series
+  pub writ_series: Tree, // {writ_id}{author_id}{series_id}: {author_id}{series_id}
+  pub media: Tree, // {hex hash}: MediaMeta
+  pub writ_media: Tree, // {writ_id}: BTreeSet<{hex hash}>
+  pub writ_collaborators: Tree, // {writ_id}: Vec<Collaborator>
+  pub user_collaborations: Tree, // {user_id}{writ_id}: CollaboratorRole
+  pub writ_views: Tree, // {writ_id}{day}: DailyViews
+  pub site_views: Tree, // {day}{writ_id}: DailyViews
+  pub writ_referrers: Tree, // {writ_id}{day}{referrer host}: u64
+  pub view_visitors: Tree, // {day}{salted visitor hash}: ()
+  pub view_salts: Tree, // {day}: salt
+  pub related_writs: Tree, // {writ_id}: RelatedWrits
+  pub tag_changes: Tree, // {tag}: when a writ last gained, lost or hid it
+  pub share_links: Tree, // {token}: ShareLink
+  pub writ_share_links: Tree, // {writ_id}{token}: ()
+  pub writ_reactions: Tree, // {writ_id}>{reaction}: count
+  pub writ_reactors: Tree, // {writ_id}<{user_id}>{reaction}: when
   // comments
   pub comment_settings: Tree,
   pub comment_key_path_index: Tree,
@@ -65,6 +98,8 @@ pub struct Orchestrator {
   pub comment_raw_content: Tree,
   pub comment_voters: Tree, // comment_id_user_id: {up_or_down, when}
   pub comment_votes: Tree,  // comment_id: {up, down, votes, when}
+  pub comment_reactions: Tree, // {comment_id}>{reaction}: count
+  pub comment_reactors: Tree, // {comment_id}<{user_id}>{reaction}: when
 }
 
 impl Orchestrator {
@@ -143,11 +178,16 @@ impl Orchestrator {
     let writs = db.open_tree("writs").unwrap();
     let raw_content = db.open_tree("raw_content").unwrap();
     let content = db.open_tree("content").unwrap();
+    crate::writs::migrate_writ_layouts(&db, &writs, &raw_content, &content, &hasher);
     let tags_index = db.open_tree("tags_index").unwrap();
     let tag_counter = db.open_tree("tag_counter").unwrap();
+    let public_tags = db.open_tree("public_tags").unwrap();
+    let public_tag_counter = db.open_tree("public_tag_counter").unwrap();
+    crate::tags::index_public_tags(&db, &writs, &public_tags, &public_tag_counter);
 
     let slugs = db.open_tree("slugs").unwrap();
     let kinds = db.open_tree("kinds").unwrap();
+    crate::kinds::seed_kinds(&kinds, &CONF.read().kinds);
 
     let titles = db.open_tree("titles").unwrap();
     let comment_trees = db.open_tree("comment_trees").unwrap();
@@ -160,6 +200,38 @@ impl Orchestrator {
     let votes = db.open_tree("votes").unwrap();
     let comment_votes = db.open_tree("comment_votes").unwrap();
     let dates = db.open_tree("dates").unwrap();
+    crate::writs::migrate_date_index(&db, &dates, &writs);
+    let archive_months = db.open_tree("archive_months").unwrap();
+    crate::date_archive::count_archive_months(&db, &writs, &archive_months);
+    let writ_revisions = db.open_tree("writ_revisions").unwrap();
+    let scheduled_writs = db.open_tree("scheduled_writs").unwrap();
+    let search_index = db.open_tree("search_index").unwrap();
+    let search_terms = db.open_tree("search_terms").unwrap();
+    crate::search::backfill_search_index(&db, &writs, &raw_content, &content, &search_index, &search_terms);
+    let writ_ranks = db.open_tree("writ_ranks").unwrap();
+    let writ_rankings = db.open_tree("writ_rankings").unwrap();
+    crate::rankings::backfill_writ_ranks(&db, &writs, &votes, &comments, &comment_key_path_index, &writ_rankings, &writ_ranks);
+    let retired_slugs = db.open_tree("retired_slugs").unwrap();
+    let slug_history = db.open_tree("slug_history").unwrap();
+    let series = db.open_tree("series").unwrap();
+    let writ_series = db.open_tree("writ_series").unwrap();
+    let media = db.open_tree("media").unwrap();
+    let writ_media = db.open_tree("writ_media").unwrap();
+    let writ_collaborators = db.open_tree("writ_collaborators").unwrap();
+    let user_collaborations = db.open_tree("user_collaborations").unwrap();
+    let writ_views = db.open_tree("writ_views").unwrap();
+    let site_views = db.open_tree("site_views").unwrap();
+    let writ_referrers = db.open_tree("writ_referrers").unwrap();
+    let view_visitors = db.open_tree("view_visitors").unwrap();
+    let view_salts = db.open_tree("view_salts").unwrap();
+    let related_writs = db.open_tree("related_writs").unwrap();
+    let tag_changes = db.open_tree("tag_changes").unwrap();
+    let share_links = db.open_tree("share_links").unwrap();
+    let writ_share_links = db.open_tree("writ_share_links").unwrap();
+    let writ_reactions = db.open_tree("writ_reactions").unwrap();
+    let writ_reactors = db.open_tree("writ_reactors").unwrap();
+    let comment_reactions = db.open_tree("comment_reactions").unwrap();
+    let comment_reactors = db.open_tree("comment_reactors").unwrap();
 
     Orchestrator {
       db,
@@ -199,6 +271,8 @@ impl Orchestrator {
       kinds,
       tags_index,
       tag_counter,
+      public_tags,
+      public_tag_counter,
       titles,
       comment_trees,
       comment_key_path_index,
@@ -210,6 +284,34 @@ impl Orchestrator {
       comment_voters,
       comment_votes,
       dates,
+      archive_months,
+      writ_revisions,
+      scheduled_writs,
+      search_index,
+      search_terms,
+      writ_ranks,
+      writ_rankings,
+      retired_slugs,
+      slug_history,
+      series,
+      writ_series,
+      media,
+      writ_media,
+      writ_collaborators,
+      user_collaborations,
+      writ_views,
+      site_views,
+      writ_referrers,
+      view_visitors,
+      view_salts,
+      related_writs,
+      tag_changes,
+      share_links,
+      writ_share_links,
+      writ_reactions,
+      writ_reactors,
+      comment_reactions,
+      comment_reactors,
     }
   }
 }
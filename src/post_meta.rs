@@ -0,0 +1,210 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use std::lazy::SyncLazy;
+
+use super::CONF;
+use crate::{
+  kinds::WritKind,
+  orchestrator::ORC,
+  search::strip_html,
+  utils::datetime_from_unix_timestamp,
+  writs::{PublicWrit, Writ},
+};
+
+const SITE_NAME: &str = "Kurshok";
+const DESCRIPTION_LENGTH: usize = 160;
+
+static FIRST_IMAGE_REGEX: SyncLazy<Regex> = SyncLazy::new(|| {
+  Regex::new(r#"<img\b[^>]*\bsrc="([^"]+)""#).unwrap()
+});
+
+// the bits of markdown that aren't prose, images and code go entirely, links keep their text
+static MD_SYNTAX_REGEXES: SyncLazy<Vec<(Regex, &'static str)>> = SyncLazy::new(|| {
+  vec![
+    (Regex::new(r"(?s)```.*?```").unwrap(), " "),
+    (Regex::new(r"!\[[^\]]*\]\([^)]*\)").unwrap(), " "),
+    (Regex::new(r"\[([^\]]*)\]\([^)]*\)").unwrap(), "$1"),
+    (Regex::new(r"(?m)^\s{0,3}(#{1,6}|>|[-*+]|\d+\.)\s+").unwrap(), ""),
+    (Regex::new(r"[*_~`]+").unwrap(), ""),
+    (Regex::new(r"<[^>]*>").unwrap(), " "),
+  ]
+});
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct PostMeta {
+  pub site_name: String,
+  pub title: String,
+  pub description: String,
+  pub image: Option<String>,
+  pub canonical_url: String,
+  pub author: String,
+  pub published: String, // rfc3339
+  pub modified: String,
+  pub tags: Vec<String>,
+  pub twitter_card: String,
+  pub json_ld: String, // safe to drop straight into a script tag
+}
+
+impl PostMeta {
+  pub fn of(pw: &PublicWrit, writ: &Writ, writ_kind: &WritKind) -> Self {
+    let domain = CONF.read().domain.clone();
+    let content = pw.content.as_deref().unwrap_or("");
+
+    let description = excerpt(&description_source(writ, content), DESCRIPTION_LENGTH);
+    let image = first_image(content, &domain);
+    let canonical_url = format!("https://{}{}", domain, writ_kind.slug_path(&writ.slug));
+    let published = rfc3339(writ.posted);
+    // writs from before modified times were kept only have their posting time
+    let modified = rfc3339(writ.modified.max(writ.posted));
+
+    let mut authors = vec![json!({ "@type": "Person", "name": pw.author_name })];
+    for co_author in pw.co_authors.iter() {
+      authors.push(json!({ "@type": "Person", "name": co_author.name }));
+    }
+
+    let mut ld = json!({
+      "@context": "https://schema.org",
+      "@type": "BlogPosting",
+      "headline": pw.title,
+      "description": description,
+      "url": canonical_url,
+      "mainEntityOfPage": { "@type": "WebPage", "@id": canonical_url },
+      "author": authors,
+      "datePublished": published,
+      "dateModified": modified,
+      "keywords": pw.tags.join(", "),
+      "wordCount": pw.word_count,
+      "publisher": { "@type": "Organization", "name": SITE_NAME, "url": format!("https://{}/", domain) },
+    });
+    if let Some(image) = &image {
+      ld["image"] = json!(image);
+    }
+
+    Self {
+      site_name: SITE_NAME.to_string(),
+      title: pw.title.clone(),
+      twitter_card: if image.is_some() { "summary_large_image" } else { "summary" }.to_string(),
+      description,
+      image,
+      canonical_url,
+      author: pw.author_name.clone(),
+      published,
+      modified,
+      tags: pw.tags.clone(),
+      // a "</script>" inside a string would end the tag early
+      json_ld: ld.to_string().replace("</", "<\\/"),
+    }
+  }
+}
+
+// markdown writs are summarised from what was written, html ones from what shows
+fn description_source(writ: &Writ, content: &str) -> String {
+  if writ.is_md {
+    let raw = writ
+      .writ_id()
+      .and_then(|wid| ORC.raw_content.get(wid.to_bin()).ok().flatten())
+      .map(|raw| String::from_utf8_lossy(&raw).to_string());
+    if let Some(raw) = raw {
+      return MD_SYNTAX_REGEXES
+        .iter()
+        .fold(raw, |text, (re, rep)| re.replace_all(&text, *rep).to_string());
+    }
+  }
+  strip_html(content)
+}
+
+pub fn excerpt(text: &str, max_len: usize) -> String {
+  let text = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+  if text.chars().count() <= max_len {
+    return text;
+  }
+
+  let mut excerpt = String::new();
+  for word in text.split(' ') {
+    if excerpt.chars().count() + word.chars().count() + 1 > max_len - 1 {
+      break;
+    }
+    if !excerpt.is_empty() {
+      excerpt.push(' ');
+    }
+    excerpt.push_str(word);
+  }
+  if excerpt.is_empty() {
+    excerpt = text.chars().take(max_len - 1).collect();
+  }
+  excerpt.push('…');
+  excerpt
+}
+
+// crawlers need absolute urls, anything that isn't one or site relative is left out
+pub fn first_image(html: &str, domain: &str) -> Option<String> {
+  let src = FIRST_IMAGE_REGEX.captures(html)?.get(1)?.as_str().replace("&amp;", "&");
+  if src.starts_with("https://") || src.starts_with("http://") {
+    Some(src)
+  } else if src.starts_with("//") {
+    Some(format!("https:{}", src))
+  } else if src.starts_with('/') {
+    Some(format!("https://{}{}", domain, src))
+  } else {
+    None
+  }
+}
+
+fn rfc3339(timestamp: i64) -> String {
+  datetime_from_unix_timestamp(timestamp).format(time::Format::Rfc3339)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn short_excerpts_only_get_their_whitespace_squashed() {
+    assert_eq!(excerpt("  a  quiet\n\tpost ", 160), "a quiet post");
+    assert_eq!(excerpt("", 160), "");
+  }
+
+  #[test]
+  fn long_excerpts_stop_at_a_word_and_fit() {
+    let cut = excerpt("one two three four", 10);
+    assert_eq!(cut, "one two…");
+    assert!(cut.chars().count() <= 10);
+
+    // multibyte text is measured in chars, not bytes
+    let cut = excerpt("ünïcödé wörds äll thë wäy döwn", 12);
+    assert_eq!(cut, "ünïcödé…");
+    assert!(cut.chars().count() <= 12);
+  }
+
+  #[test]
+  fn a_word_longer_than_the_excerpt_gets_chopped() {
+    assert_eq!(excerpt("abcdefghijkl", 5), "abcd…");
+  }
+
+  #[test]
+  fn first_image_is_made_absolute() {
+    let domain = "kurshok.space";
+    assert_eq!(
+      first_image(r#"<p><img alt="x" src="https://cdn.example.com/a.png?w=1&amp;h=2"></p>"#, domain),
+      Some("https://cdn.example.com/a.png?w=1&h=2".to_string())
+    );
+    assert_eq!(
+      first_image(r#"<img src="//cdn.example.com/b.png">"#, domain),
+      Some("https://cdn.example.com/b.png".to_string())
+    );
+    assert_eq!(
+      first_image(r#"<img src="/media/c.png"><img src="https://cdn.example.com/d.png">"#, domain),
+      Some("https://kurshok.space/media/c.png".to_string())
+    );
+  }
+
+  #[test]
+  fn no_usable_first_image() {
+    let domain = "kurshok.space";
+    assert_eq!(first_image("<p>no pictures here</p>", domain), None);
+    assert_eq!(first_image(r#"<img src="relative/e.png">"#, domain), None);
+    assert_eq!(first_image(r#"<imgur src="/media/f.png">"#, domain), None);
+  }
+}
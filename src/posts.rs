@@ -1,11 +1,15 @@
-use actix_web::{get, web, HttpRequest, HttpResponse};
+use actix_web::{get, http::{header, StatusCode}, web, HttpRequest, HttpResponse};
 use tera::Context;
 use time::Duration;
 
 use super::TEMPLATES;
 
 use crate::{
+    caching::{add_validators, cache_render, cached_render, etag_of, is_fresh, PAGE_CACHE_CONTROL, PRIVATE_CACHE_CONTROL},
+    kinds::is_registered_kind_path,
     orchestrator::ORC,
+    post_meta::PostMeta,
+    share_links::ShareQuery,
 //  utils::FancyIVec,
     writs::{
         WritID,
@@ -13,13 +17,16 @@ use crate::{
     }
 };
 
-#[get("/post/{author_id}:{writ_id}")]
+// how many "you might also like" writs go under a post
+const RELATED_ON_PAGE: usize = 5;
+
+#[get("/{prefix}/{author_id}:{writ_id}", guard = "is_registered_kind_path")]
 pub async fn render_post(
-    id_parts: web::Path<(u64, u64)>,
+    id_parts: web::Path<(String, u64, u64)>,
+    share: web::Query<ShareQuery>,
     req: HttpRequest,
 ) -> HttpResponse {
-    let (author_id, writ_unique_id) = id_parts.into_inner();
-    let writ_id = format!("post:{}:{}", author_id, writ_unique_id);
+    let (prefix, author_id, writ_unique_id) = id_parts.into_inner();
 
     let (o_usr, potential_renewal_cookie) = ORC.user_by_session_renew(&req, Duration::days(3));
 
@@ -29,50 +36,118 @@ pub async fn render_post(
         ctx.insert("dev_mode", &ORC.dev_mode);
     }
 
+    let writ_kind = match ORC.writ_kind_by_prefix(&prefix) {
+        Some(wk) => wk,
+        None => {
+            return render_404(
+                &mut ctx,
+                "We don't have that kind of writ.",
+                ORC.dev_mode,
+            )
+        }
+    };
+    let writ_id = format!("{}:{}:{}", writ_kind.kind, author_id, writ_unique_id);
+
+    // anonymous readers all get the same page
+    let anonymous = o_usr.is_none() && share.share.is_none();
+    if anonymous {
+        if let Some(res) = cached_post_page(&req, &writ_id) {
+            return res;
+        }
+    }
+
+    // a share link gets outside readers into a private writ, and nowhere else
+    let shared_writ = share.share.as_deref().and_then(|token| {
+        ORC.shared_public_writ(token, &WritID::from_str(&writ_id)?)
+    });
+
     let mut query = WritQuery::default();
     query.ids = Some(vec![writ_id]);
     query.public = Some(true);
     query.amount = Some(1);
+    query.kind = writ_kind.kind.clone();
 
-    let public_writ = match ORC.public_writ_query(query, o_usr.as_ref()) {
-        Some(mut writs) => writs.pop().unwrap(),
+    let public_writ = match ORC.public_writ_query(query, o_usr.as_ref()).and_then(|mut writs| writs.pop()) {
+        Some(pw) => pw,
+        None if shared_writ.is_some() => {
+            ctx.insert("shared", &true);
+            shared_writ.unwrap()
+        },
         None => {
             return render_404(
                 &mut ctx,
-                "We couldn't find any posts with that id.",
+                &format!("We couldn't find any {}s with that id.", writ_kind.display_name.to_lowercase()),
                 ORC.dev_mode,
             )
         }
     };
 
     ctx.insert("public_writ", &public_writ);
+    ctx.insert("writ_kind", &writ_kind);
+    insert_series_nav(&mut ctx, &public_writ.id, o_usr.as_ref().map(|usr| usr.id));
+    if let Some(writ_id) = WritID::from_str(&public_writ.id) {
+        let viewer = o_usr.as_ref().map(|usr| usr.id);
+        ORC.record_writ_view(&req, &writ_id, viewer);
+        ctx.insert("related", &ORC.related_writs_for(&writ_id, viewer, RELATED_ON_PAGE));
+        if let Some(writ) = ORC.writ_by_id_bytes(&writ_id.to_bin()) {
+            ctx.insert("meta", &PostMeta::of(&public_writ, &writ, &writ_kind));
+        }
+    }
 
     let mut res = HttpResponse::Ok();
     render_template(
         &mut ctx,
-        "post.html",
+        &writ_kind.template,
+        &req,
         match potential_renewal_cookie {
             Some(c) => res.cookie(c),
             None => &mut res,
         },
+        if anonymous { Some(public_writ.id.as_str()) } else { None },
         ORC.dev_mode,
     )
 }
 
-#[get("/post/{slug}")]
+#[get("/{prefix}/{slug}", guard = "is_registered_kind_path")]
 pub async fn render_post_by_slug(
-    slug: web::Path<String>,
+    path: web::Path<(String, String)>,
     req: HttpRequest,
 ) -> HttpResponse {
     let mut ctx = Context::new();
 
-    let slug_key = format!("post:{}", slug.into_inner());
+    let (prefix, slug) = path.into_inner();
+    let writ_kind = match ORC.writ_kind_by_prefix(&prefix) {
+        Some(wk) => wk,
+        None => {
+            return render_404(
+                &mut ctx,
+                "We don't have that kind of writ.",
+                ORC.dev_mode,
+            )
+        }
+    };
+
+    let slug_key = format!("{}:{}", writ_kind.kind, slug);
     let writ_id = if let Ok(Some(writ_id)) = ORC.slugs.get(slug_key.as_bytes()) {
         WritID::from_bin(&writ_id).to_string()
+    } else if let Some(writ) = ORC
+        .retired_slug_owner(&writ_kind.kind, &slug)
+        .and_then(|wid| ORC.writ_by_id_bytes(&wid))
+        .filter(|writ| writ.public)
+    {
+        // the writ got renamed, send people to where it lives now
+        let location = match ORC.writ_kind(&writ.kind) {
+            Some(wk) => wk.slug_path(&writ.slug),
+            None => writ_kind.slug_path(&writ.slug),
+        };
+        return HttpResponse::MovedPermanently()
+            .append_header((header::LOCATION, location))
+            .finish()
+            .into_body();
     } else {
         return render_404(
             &mut ctx,
-            "That's a bad slug, we couldn't find any posts matching it.",
+            "That's a bad slug, we couldn't find anything matching it.",
             ORC.dev_mode,
         );
     };
@@ -81,38 +156,89 @@ pub async fn render_post_by_slug(
 
     if let Some(usr) = &o_usr {
         ctx.insert("user", usr);
+    } else if let Some(res) = cached_post_page(&req, &writ_id) {
+        return res;
     }
 
     let mut query = WritQuery::default();
     query.ids = Some(vec![writ_id]);
     query.public = Some(true);
     query.amount = Some(1);
+    query.kind = writ_kind.kind.clone();
 
     let public_writ = match ORC.public_writ_query(query, o_usr.as_ref()) {
         Some(mut writs) => writs.pop().unwrap(),
         None => {
             return render_404(
                 &mut ctx,
-                "That's a bad slug, we couldn't find any posts matching it.",
+                "That's a bad slug, we couldn't find anything matching it.",
                 ORC.dev_mode,
             );
         }
     };
 
     ctx.insert("public_writ", &public_writ);
+    ctx.insert("writ_kind", &writ_kind);
+    insert_series_nav(&mut ctx, &public_writ.id, o_usr.as_ref().map(|usr| usr.id));
+    if let Some(writ_id) = WritID::from_str(&public_writ.id) {
+        let viewer = o_usr.as_ref().map(|usr| usr.id);
+        ORC.record_writ_view(&req, &writ_id, viewer);
+        ctx.insert("related", &ORC.related_writs_for(&writ_id, viewer, RELATED_ON_PAGE));
+        if let Some(writ) = ORC.writ_by_id_bytes(&writ_id.to_bin()) {
+            ctx.insert("meta", &PostMeta::of(&public_writ, &writ, &writ_kind));
+        }
+    }
 
     let mut res = HttpResponse::Ok();
     render_template(
         &mut ctx,
-        "post.html",
+        &writ_kind.template,
+        &req,
         match potential_renewal_cookie {
             Some(c) => res.cookie(c),
             None => &mut res,
         },
+        if o_usr.is_none() { Some(public_writ.id.as_str()) } else { None },
         ORC.dev_mode,
     )
 }
 
+// views still count when the page comes out of the cache
+fn cached_post_page(req: &HttpRequest, writ_id: &str) -> Option<HttpResponse> {
+    let cached = cached_render(writ_id)?;
+    if let Some(wid) = WritID::from_str(writ_id) {
+        ORC.record_writ_view(req, &wid, None);
+    }
+    Some(page_response(req, &mut HttpResponse::Ok(), cached.html, &cached.etag, PAGE_CACHE_CONTROL))
+}
+
+fn page_response(
+    req: &HttpRequest,
+    res: &mut actix_web::dev::HttpResponseBuilder,
+    html: String,
+    etag: &str,
+    cache_control: &str,
+) -> HttpResponse {
+    let res = add_validators(res, etag, None, cache_control);
+    if is_fresh(req, etag) {
+        return res.status(StatusCode::NOT_MODIFIED).finish().into_body();
+    }
+    res.content_type("text/html").body(html)
+}
+
+// the first series the writ is in gets its contents and neighbours shown
+fn insert_series_nav(ctx: &mut Context, writ_id: &str, viewer: Option<u64>) {
+    if let Some(wid) = WritID::from_str(writ_id) {
+        let nav = ORC
+            .series_of_writ(&wid.to_bin())
+            .iter()
+            .find_map(|series| ORC.series_nav(series, writ_id, viewer));
+        if let Some(nav) = nav {
+            ctx.insert("series", &nav);
+        }
+    }
+}
+
 fn render_404(ctx: &mut Context, message: &str, dev_mode: bool) -> HttpResponse {
     ctx.insert("message", &message);
     ctx.insert("dev_mode", &dev_mode);
@@ -135,12 +261,26 @@ fn render_404(ctx: &mut Context, message: &str, dev_mode: bool) -> HttpResponse
 fn render_template(
     ctx: &mut Context,
     name: &str,
+    req: &HttpRequest,
     res: &mut actix_web::dev::HttpResponseBuilder,
+    cache_as: Option<&str>,
     dev_mode: bool,
 ) -> HttpResponse {
     ctx.insert("dev_mode", &dev_mode);
     match TEMPLATES.read().render(name, &ctx) {
-        Ok(s) => res.content_type("text/html").body(s),
+        Ok(s) => {
+            let etag = etag_of(s.as_bytes());
+            // only the page every anonymous reader gets may sit in shared caches,
+            // a signed in or share link view is that one reader's alone
+            let cache_control = match cache_as {
+                Some(writ_id) => {
+                    cache_render(writ_id, s.clone(), etag.clone());
+                    PAGE_CACHE_CONTROL
+                },
+                None => PRIVATE_CACHE_CONTROL,
+            };
+            page_response(req, res, s, &etag, cache_control)
+        },
         Err(err) => {
             if dev_mode {
                 HttpResponse::InternalServerError()
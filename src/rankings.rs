@@ -0,0 +1,249 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+use sled::transaction::*;
+
+use std::collections::HashMap;
+
+use crate::orchestrator::Orchestrator;
+use crate::utils::{unix_timestamp, FancyIVec};
+use crate::writs::Writ;
+
+// hot scores count from here so the time part stays small
+const HOT_EPOCH: i64 = 1609459200;
+// seconds it takes for a post to need ten times the votes to stay level
+const HOT_DECAY: f64 = 45000.0;
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum WritSort {
+  New,
+  Top,
+  Hot,
+  Discussed,
+}
+
+impl std::default::Default for WritSort {
+  fn default() -> Self {
+    WritSort::New
+  }
+}
+
+impl WritSort {
+  pub fn is_ranked(&self) -> bool {
+    *self != WritSort::New
+  }
+
+  fn prefix(&self) -> u8 {
+    match self {
+      WritSort::New => b'n',
+      WritSort::Top => b't',
+      WritSort::Hot => b'h',
+      WritSort::Discussed => b'd',
+    }
+  }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum TopWindow {
+  Day,
+  Week,
+  Month,
+  Year,
+  All,
+}
+
+impl TopWindow {
+  pub fn seconds(&self) -> Option<i64> {
+    match self {
+      TopWindow::Day => Some(86400),
+      TopWindow::Week => Some(86400 * 7),
+      TopWindow::Month => Some(86400 * 30),
+      TopWindow::Year => Some(86400 * 365),
+      TopWindow::All => None,
+    }
+  }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, PartialEq, Debug)]
+pub struct WritRanks {
+  pub votes: i64,
+  pub comments: u64,
+  pub posted: i64,
+}
+
+impl WritRanks {
+  pub fn new(posted: i64) -> Self {
+    Self {
+      votes: 0,
+      comments: 0,
+      posted,
+    }
+  }
+
+  // big endian friendly scores so the rankings tree sorts them properly
+  pub fn score(&self, sort: WritSort) -> u64 {
+    match sort {
+      WritSort::New => self.posted as u64 ^ (1 << 63),
+      WritSort::Top => self.votes as u64 ^ (1 << 63),
+      WritSort::Hot => sortable_f64(hot_score(self.votes, self.posted)),
+      WritSort::Discussed => self.comments,
+    }
+  }
+
+  pub fn keys(&self, wid: &[u8]) -> Vec<Vec<u8>> {
+    [WritSort::Top, WritSort::Hot, WritSort::Discussed]
+      .iter()
+      .map(|sort| rank_key(*sort, wid, self.score(*sort)))
+      .collect()
+  }
+}
+
+impl Orchestrator {
+  pub fn writ_ranks(&self, wid: &[u8]) -> Option<WritRanks> {
+    match self.writ_ranks.get(wid) {
+      Ok(Some(raw)) => Some(WritRanks::try_from_slice(&raw).unwrap()),
+      _ => None,
+    }
+  }
+
+  pub fn insert_writ_ranks_in_transaction(
+    &self,
+    rankings: &TransactionalTree,
+    ranks: &TransactionalTree,
+    wid: &[u8],
+    writ_ranks: &WritRanks,
+  ) -> ConflictableTransactionResult<(), ()> {
+    for key in writ_ranks.keys(wid) {
+      rankings.insert(key, wid)?;
+    }
+    ranks.insert(wid, writ_ranks.try_to_vec().unwrap())?;
+    Ok(())
+  }
+
+  pub fn remove_writ_ranks_in_transaction(
+    &self,
+    rankings: &TransactionalTree,
+    ranks: &TransactionalTree,
+    wid: &[u8],
+  ) -> ConflictableTransactionResult<(), ()> {
+    if let Some(raw) = ranks.remove(wid)? {
+      for key in WritRanks::try_from_slice(&raw).unwrap().keys(wid) {
+        rankings.remove(key)?;
+      }
+    }
+    Ok(())
+  }
+
+  // writs from before rankings existed have nothing to update, they're simply left unranked
+  pub fn update_writ_ranks_in_transaction<F: Fn(&mut WritRanks)>(
+    &self,
+    rankings: &TransactionalTree,
+    ranks: &TransactionalTree,
+    wid: &[u8],
+    update: F,
+  ) -> ConflictableTransactionResult<(), ()> {
+    let mut writ_ranks = match ranks.get(wid)? {
+      Some(raw) => WritRanks::try_from_slice(&raw).unwrap(),
+      None => return Ok(()),
+    };
+
+    for key in writ_ranks.keys(wid) {
+      rankings.remove(key)?;
+    }
+    update(&mut writ_ranks);
+    self.insert_writ_ranks_in_transaction(rankings, ranks, wid, &writ_ranks)
+  }
+}
+
+const RANKINGS_BACKFILL_MIGRATION: &[u8] = b"migration:writ_rankings_backfill";
+
+// writs from before rankings existed were left out of them,
+// so rank them once from their votes and however many comments they've gathered
+pub fn backfill_writ_ranks(
+  db: &sled::Db,
+  writs: &sled::Tree,
+  votes: &sled::Tree,
+  comments: &sled::Tree,
+  comment_key_path_index: &sled::Tree,
+  rankings: &sled::Tree,
+  ranks: &sled::Tree,
+) {
+  if db.contains_key(RANKINGS_BACKFILL_MIGRATION).unwrap_or(false) {
+    return;
+  }
+
+  // replies are stored under their own id, only their key path says which writ they're on
+  let mut comment_counts: HashMap<String, u64> = HashMap::new();
+  for res in comments.iter().keys() {
+    let id = res.expect("failed to read the comments tree while backfilling rankings").to_string();
+    let full_id = if id.contains('/') {
+      Some(id)
+    } else {
+      comment_key_path_index.get(id.as_bytes()).unwrap().map(|raw| raw.to_string())
+    };
+    if let Some(writ_id) = full_id.as_ref().and_then(|id| id.split('/').next()) {
+      *comment_counts.entry(writ_id.to_string()).or_insert(0) += 1;
+    }
+  }
+
+  let mut ranked = 0;
+  for res in writs.iter() {
+    let (wid, raw) = res.expect("failed to read the writs tree while backfilling rankings");
+    if ranks.contains_key(&wid).unwrap() {
+      continue;
+    }
+
+    let writ = Writ::try_from_slice(&raw).unwrap();
+    let writ_ranks = WritRanks {
+      votes: votes.get(&wid).unwrap().map_or(0, |raw| raw.to_i64()),
+      comments: comment_counts.get(&writ.id).copied().unwrap_or(0),
+      posted: writ.posted,
+    };
+
+    for key in writ_ranks.keys(&wid) {
+      rankings.insert(key, &wid).unwrap();
+    }
+    ranks.insert(&wid, writ_ranks.try_to_vec().unwrap()).unwrap();
+    ranked += 1;
+  }
+
+  rankings.flush().unwrap();
+  ranks.flush().unwrap();
+  db.insert(RANKINGS_BACKFILL_MIGRATION, &unix_timestamp().to_be_bytes()).unwrap();
+  if ranked > 0 {
+    println!("ranked {} writs from before there were rankings", ranked);
+  }
+}
+
+pub fn hot_score(votes: i64, posted: i64) -> f64 {
+  let order = (votes.abs().max(1) as f64).log10();
+  votes.signum() as f64 * order + (posted - HOT_EPOCH) as f64 / HOT_DECAY
+}
+
+fn sortable_f64(f: f64) -> u64 {
+  let bits = f.to_bits();
+  if f.is_sign_negative() {
+    !bits
+  } else {
+    bits | (1 << 63)
+  }
+}
+
+#[inline]
+pub fn rank_key(sort: WritSort, wid: &[u8], score: u64) -> Vec<u8> {
+  let mut key = Vec::with_capacity(33);
+  key.push(sort.prefix());
+  key.extend_from_slice(&wid[0..4]);
+  key.extend_from_slice(&score.to_be_bytes());
+  key.extend_from_slice(wid);
+  key
+}
+
+#[inline]
+pub fn rank_scan_prefix(sort: WritSort, kind: &str) -> Vec<u8> {
+  let mut prefix = Vec::with_capacity(5);
+  prefix.push(sort.prefix());
+  prefix.extend_from_slice(kind.as_bytes());
+  prefix
+}
@@ -0,0 +1,277 @@
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use sled::{transaction::*, IVec, Transactional, Tree};
+use thiserror::Error;
+use time::Duration;
+
+use std::collections::HashMap;
+
+use super::CONF;
+use crate::{
+  caching::forget_render,
+  comments::Comment,
+  orchestrator::{Orchestrator, ORC},
+  responses,
+  utils::{unix_timestamp, FancyIVec},
+  writs::Writ,
+};
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct Reaction {
+  pub name: String, // what goes in urls and you_reacted
+  pub emoji: String,
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct ReactionCount {
+  pub name: String,
+  pub emoji: String,
+  pub count: u64,
+}
+
+#[derive(Error, Debug)]
+pub enum ReactionError {
+  #[error("that's not a reaction we have")]
+  UnknownReaction,
+  #[error("you already reacted with that")]
+  AlreadyReacted,
+  #[error("you haven't reacted with that, there's nothing to take back")]
+  NotReacted,
+  #[error("failed to register reaction")]
+  DBIssue,
+}
+
+pub fn default_reactions() -> Vec<Reaction> {
+  [
+    ("like", "👍"),
+    ("love", "❤️"),
+    ("laugh", "😂"),
+    ("wow", "😮"),
+    ("sad", "😢"),
+    ("celebrate", "🎉"),
+  ]
+  .iter()
+  .map(|(name, emoji)| Reaction { name: name.to_string(), emoji: emoji.to_string() })
+  .collect()
+}
+
+pub fn is_configured_reaction(name: &str) -> bool {
+  CONF.read().reactions.iter().any(|r| r.name == name)
+}
+
+// {item_id}>{reaction}: count
+#[inline]
+pub fn reaction_count_key(item_id: &str, reaction: &str) -> String {
+  format!("{}>{}", item_id, reaction)
+}
+
+// {item_id}<{user_id}>{reaction}: when
+#[inline]
+pub fn reactor_key(item_id: &str, usr_id: u64, reaction: &str) -> String {
+  format!("{}<{}>{}", item_id, usr_id, reaction)
+}
+
+impl Orchestrator {
+  // writs and comments keep their reactions in separate trees, but they work the same way
+  pub fn react(
+    &self,
+    counts: &Tree,
+    reactors: &Tree,
+    item_id: &str,
+    usr_id: u64,
+    reaction: &str,
+    add: bool,
+  ) -> Result<u64, ReactionError> {
+    if !is_configured_reaction(reaction) {
+      return Err(ReactionError::UnknownReaction);
+    }
+    let count_key = reaction_count_key(item_id, reaction);
+    let reactor_key = reactor_key(item_id, usr_id, reaction);
+
+    let res: TransactionResult<u64, ReactionError> = (counts, reactors).transaction(|(counts, reactors)| {
+      let mut count = counts.get(count_key.as_bytes())?.map_or(0, |raw| raw.to_u64());
+
+      // no reacting twice with the same thing, and nothing to take back that wasn't given
+      if reactors.get(reactor_key.as_bytes())?.is_some() == add {
+        return Err(ConflictableTransactionError::Abort(
+          if add { ReactionError::AlreadyReacted } else { ReactionError::NotReacted }
+        ));
+      }
+
+      if add {
+        count += 1;
+        reactors.insert(reactor_key.as_bytes(), &unix_timestamp().to_be_bytes())?;
+      } else {
+        count = count.saturating_sub(1);
+        reactors.remove(reactor_key.as_bytes())?;
+      }
+
+      if count == 0 {
+        counts.remove(count_key.as_bytes())?;
+      } else {
+        counts.insert(count_key.as_bytes(), IVec::from_u64(count))?;
+      }
+      Ok(count)
+    });
+
+    match res {
+      Ok(count) => Ok(count),
+      Err(TransactionError::Abort(e)) => Err(e),
+      Err(e) => {
+        if self.dev_mode {
+          println!("Something bad went down with reacting - {:?}", e);
+        }
+        Err(ReactionError::DBIssue)
+      }
+    }
+  }
+
+  // in the configured order, reactions that have since been dropped from the config aren't shown
+  pub fn reaction_counts(&self, counts: &Tree, item_id: &str) -> Vec<ReactionCount> {
+    let prefix = format!("{}>", item_id);
+    let found: HashMap<String, u64> = counts
+      .scan_prefix(prefix.as_bytes())
+      .filter_map(|res| res.ok())
+      .map(|(key, raw)| (String::from_utf8_lossy(&key[prefix.len()..]).to_string(), raw.to_u64()))
+      .collect();
+
+    if found.is_empty() {
+      return vec![];
+    }
+
+    CONF.read().reactions
+      .iter()
+      .filter_map(|r| found.get(&r.name).map(|count| ReactionCount {
+        name: r.name.clone(),
+        emoji: r.emoji.clone(),
+        count: *count,
+      }))
+      .collect()
+  }
+
+  pub fn you_reacted(&self, reactors: &Tree, item_id: &str, usr_id: &Option<u64>) -> Vec<String> {
+    let usr_id = match usr_id {
+      Some(id) => id,
+      None => return vec![],
+    };
+    let prefix = format!("{}<{}>", item_id, usr_id);
+    reactors
+      .scan_prefix(prefix.as_bytes())
+      .keys()
+      .filter_map(|res| res.ok())
+      .map(|key| String::from_utf8_lossy(&key[prefix.len()..]).to_string())
+      .collect()
+  }
+
+  // comment ids nest, so whatever hangs under the item goes too
+  pub fn forget_reactions(&self, counts: &Tree, reactors: &Tree, item_id: &str) {
+    for (tree, sep) in [(counts, '>'), (reactors, '<'), (counts, '/'), (reactors, '/')].iter() {
+      let prefix = format!("{}{}", item_id, sep);
+      for key in tree.scan_prefix(prefix.as_bytes()).keys().filter_map(|res| res.ok()) {
+        let _ = tree.remove(key);
+      }
+    }
+  }
+
+  pub fn react_to_writ(&self, writ: &Writ, usr_id: u64, reaction: &str, add: bool) -> Result<u64, ReactionError> {
+    let count = self.react(&self.writ_reactions, &self.writ_reactors, &writ.id, usr_id, reaction, add)?;
+    forget_render(&writ.id);
+    Ok(count)
+  }
+
+  pub fn react_to_comment(&self, comment: &Comment, usr_id: u64, reaction: &str, add: bool) -> Result<u64, ReactionError> {
+    self.react(&self.comment_reactions, &self.comment_reactors, &comment.id, usr_id, reaction, add)
+  }
+}
+
+fn reaction_ratelimit(usr_id: u64) -> Option<HttpResponse> {
+  let hitter = format!("rctn{}", usr_id);
+  if let Some(rl) = ORC.ratelimiter
+    .hit(hitter.as_bytes(), 60, Duration::minutes(5))
+  {
+    if rl.is_timing_out() {
+      return Some(responses::TooManyRequests(format!(
+        "too many reactions, timeout has {} minutes left.",
+        rl.minutes_left()
+      )));
+    }
+  }
+  None
+}
+
+async fn react_to_writ(req: HttpRequest, path: web::Path<(String, String)>, add: bool) -> HttpResponse {
+  let (writ_id, reaction) = path.into_inner();
+  if let Some(usr_id) = ORC.user_id_by_session(&req) {
+    if !is_configured_reaction(&reaction) {
+      return responses::BadRequest("that's not a reaction we have");
+    }
+    if let Some(res) = reaction_ratelimit(usr_id) {
+      return res;
+    }
+    let writ = match ORC.writ_by_id(&writ_id) {
+      Some(writ) if writ.public(&Some(usr_id), false).is_some() => writ,
+      _ => return responses::NotFound("no such writ"),
+    };
+    return reaction_response(ORC.react_to_writ(&writ, usr_id, &reaction, add));
+  }
+
+  responses::Forbidden("only users may react to writs")
+}
+
+async fn react_to_comment(req: HttpRequest, path: web::Path<(String, String)>, add: bool) -> HttpResponse {
+  let (id, reaction) = path.into_inner();
+  let id = id.replace("-", "/");
+  if let Some(usr_id) = ORC.user_id_by_session(&req) {
+    if !is_configured_reaction(&reaction) {
+      return responses::BadRequest("that's not a reaction we have");
+    }
+    if let Some(res) = reaction_ratelimit(usr_id) {
+      return res;
+    }
+    let comment = match Comment::from_id(id.as_bytes()) {
+      Some(comment) => comment,
+      None => return responses::NotFound("no such comment"),
+    };
+    let writ = comment.parent_writ_id().and_then(|writ_id| ORC.writ_by_id_bytes(&writ_id.to_bin()));
+    if writ.map_or(true, |writ| writ.public(&Some(usr_id), false).is_none()) {
+      return responses::NotFound("no such comment");
+    }
+    return reaction_response(ORC.react_to_comment(&comment, usr_id, &reaction, add));
+  }
+
+  responses::Forbidden("only users may react to comments")
+}
+
+// doubling up or taking back nothing is the client's mistake, only the database failing is ours
+fn reaction_response(res: Result<u64, ReactionError>) -> HttpResponse {
+  match res {
+    Ok(count) => responses::AcceptedStatusData("reaction went through", count),
+    Err(ReactionError::DBIssue) => responses::InternalServerError(ReactionError::DBIssue.to_string()),
+    Err(e) => responses::BadRequest(e.to_string()),
+  }
+}
+
+#[get("/writ/{writ_id}/react/{reaction}")]
+pub async fn react_writ(req: HttpRequest, path: web::Path<(String, String)>) -> HttpResponse {
+  react_to_writ(req, path, true).await
+}
+
+#[get("/writ/{writ_id}/unreact/{reaction}")]
+pub async fn unreact_writ(req: HttpRequest, path: web::Path<(String, String)>) -> HttpResponse {
+  react_to_writ(req, path, false).await
+}
+
+#[get("/comment/{id}/react/{reaction}")]
+pub async fn react_comment(req: HttpRequest, path: web::Path<(String, String)>) -> HttpResponse {
+  react_to_comment(req, path, true).await
+}
+
+#[get("/comment/{id}/unreact/{reaction}")]
+pub async fn unreact_comment(req: HttpRequest, path: web::Path<(String, String)>) -> HttpResponse {
+  react_to_comment(req, path, false).await
+}
+
+#[get("/reactions")]
+pub async fn list_reactions() -> HttpResponse {
+  responses::Ok(CONF.read().reactions.clone())
+}
@@ -0,0 +1,120 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use std::lazy::SyncLazy;
+
+// comrak puts an empty anchor carrying the header id right inside every header
+static HEADER_REGEX: SyncLazy<Regex> = SyncLazy::new(|| {
+  Regex::new(r#"(?s)<h([1-6])[^>]*>\s*<a [^>]*\bid="(writ-[^"]+)"[^>]*>\s*</a>(.*?)</h[1-6]>"#).unwrap()
+});
+
+static TAG_REGEX: SyncLazy<Regex> = SyncLazy::new(|| Regex::new(r"(?s)<[^>]*>").unwrap());
+
+pub const WORDS_PER_MINUTE: u64 = 230;
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct TocEntry {
+  pub level: u8,
+  pub id: String,
+  pub text: String,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub struct ReadingStats {
+  pub word_count: u64,
+  pub reading_time: u64, // minutes, rounded up
+}
+
+impl ReadingStats {
+  pub fn of(html: &str) -> Self {
+    let text = TAG_REGEX.replace_all(html, " ");
+    let word_count = text
+      .split_whitespace()
+      .filter(|word| word.chars().any(|c| c.is_alphanumeric()))
+      .count() as u64;
+
+    Self {
+      word_count,
+      reading_time: if word_count == 0 {
+        0
+      } else {
+        (word_count + WORDS_PER_MINUTE - 1) / WORDS_PER_MINUTE
+      },
+    }
+  }
+}
+
+pub fn table_of_contents(html: &str) -> Vec<TocEntry> {
+  HEADER_REGEX
+    .captures_iter(html)
+    .filter_map(|cap| {
+      let text = TAG_REGEX.replace_all(&cap[3], "").trim().to_string();
+      if text.is_empty() {
+        return None;
+      }
+      Some(TocEntry {
+        level: cap[1].parse().unwrap(),
+        id: cap[2].to_string(),
+        text,
+      })
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn header(level: u8, id: &str, inner: &str) -> String {
+    format!(
+      r##"<h{0}><a href="#{1}" aria-hidden="true" class="anchor" id="{1}"></a>{2}</h{0}>"##,
+      level, id, inner
+    )
+  }
+
+  #[test]
+  fn toc_lists_headers_in_order() {
+    let html = format!(
+      "{}<p>intro</p>{}<p>more</p>{}",
+      header(1, "writ-start", "Start"),
+      header(3, "writ-deeper", "Going <em>deeper</em>"),
+      header(2, "writ-end", "End"),
+    );
+
+    assert_eq!(table_of_contents(&html), vec![
+      TocEntry { level: 1, id: "writ-start".to_string(), text: "Start".to_string() },
+      TocEntry { level: 3, id: "writ-deeper".to_string(), text: "Going deeper".to_string() },
+      TocEntry { level: 2, id: "writ-end".to_string(), text: "End".to_string() },
+    ]);
+  }
+
+  #[test]
+  fn toc_skips_empty_and_unanchored_headers() {
+    let html = format!(
+      "{}<h2>No anchor</h2>{}",
+      header(2, "writ-empty", "  <strong></strong> "),
+      header(4, "writ-kept", "Kept"),
+    );
+
+    let toc = table_of_contents(&html);
+    assert_eq!(toc.len(), 1);
+    assert_eq!(toc[0].id, "writ-kept");
+  }
+
+  #[test]
+  fn reading_stats_count_words_outside_tags() {
+    let stats = ReadingStats::of("<p>one <a href=\"/two-three\">two</a> — three!</p><img src=\"x.png\">");
+    assert_eq!(stats.word_count, 3);
+    assert_eq!(stats.reading_time, 1);
+  }
+
+  #[test]
+  fn reading_time_rounds_up_to_whole_minutes() {
+    assert_eq!(ReadingStats::of(""), ReadingStats { word_count: 0, reading_time: 0 });
+
+    let words = |n: u64| (0..n).map(|_| "word").collect::<Vec<_>>().join(" ");
+    assert_eq!(ReadingStats::of(&words(WORDS_PER_MINUTE)).reading_time, 1);
+    assert_eq!(ReadingStats::of(&words(WORDS_PER_MINUTE + 1)).reading_time, 2);
+  }
+}
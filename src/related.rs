@@ -0,0 +1,169 @@
+use actix_web::{get, web, HttpRequest, HttpResponse};
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+use sled::IVec;
+
+use std::collections::HashMap;
+
+use crate::{
+  orchestrator::{Orchestrator, ORC},
+  responses,
+  utils::{unix_timestamp, FancyIVec},
+  writs::{PublicWrit, Writ, WritID},
+};
+
+// only a tag's newest writs get considered, popular tags would drag in half the site otherwise
+const RELATED_POSTING_SAMPLE: usize = 1000;
+// enough spares that some can drop out for visibility and still fill a list
+const RELATED_CACHE_SIZE: usize = 24;
+// votes nudge the order, they shouldn't trump sharing a rare tag
+const VOTE_WEIGHT: f64 = 0.25;
+// cached lists get recomputed after this so votes and newer writs get a look in
+const RELATED_MAX_AGE: i64 = 60 * 60 * 24;
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, PartialEq, Debug)]
+pub struct RelatedWrits {
+  pub wids: Vec<Vec<u8>>, // best match first
+  pub computed: i64,
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct RelatedWrit {
+  pub url: String,
+  #[serde(flatten)]
+  pub writ: PublicWrit,
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct RelatedQuery {
+  pub amount: Option<usize>,
+}
+
+impl Orchestrator {
+  // rarer shared tags count for more, a tag on every other writ says next to nothing,
+  // everything's scored from the indexes so no candidate writ has to be loaded
+  pub fn compute_related_writs(&self, writ: &Writ, wid: &[u8]) -> Vec<Vec<u8>> {
+    let mut scores: HashMap<IVec, f64> = HashMap::new();
+
+    for tag in writ.tags.iter() {
+      let count = self.tag_count(tag);
+      if count < 2 {
+        continue;
+      }
+      let weight = 1.0 / (count as f64).ln().max(1.0);
+
+      let public_postings = self.tag_postings(tag, &writ.kind, None)
+        .rev()
+        .filter(|candidate| self.public_tags.contains_key(candidate).unwrap_or(false))
+        .take(RELATED_POSTING_SAMPLE);
+
+      for candidate in public_postings {
+        if candidate.as_ref() != wid {
+          *scores.entry(candidate).or_insert(0.0) += weight;
+        }
+      }
+    }
+
+    let mut ranked: Vec<(IVec, f64)> = scores
+      .into_iter()
+      .map(|(candidate, tag_score)| {
+        let votes = match self.votes.get(&candidate) {
+          Ok(Some(raw)) => raw.to_i64(),
+          _ => 0,
+        };
+        let vote_score = (votes as f64).signum() * (1.0 + (votes.abs() as f64)).ln();
+        (candidate, tag_score + VOTE_WEIGHT * vote_score)
+      })
+      .collect();
+
+    ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal).then_with(|| b.0.cmp(&a.0)));
+    ranked.truncate(RELATED_CACHE_SIZE);
+    ranked.into_iter().map(|(candidate, _)| candidate.to_vec()).collect()
+  }
+
+  pub fn related_writ_ids(&self, writ: &Writ, wid: &[u8]) -> Vec<Vec<u8>> {
+    if let Ok(Some(raw)) = self.related_writs.get(wid) {
+      let cached = RelatedWrits::try_from_slice(&raw).unwrap();
+      let fresh = unix_timestamp() - cached.computed < RELATED_MAX_AGE
+        && !writ.tags.iter().any(|tag| self.tag_changed_since(tag, cached.computed));
+      if fresh {
+        return cached.wids;
+      }
+    }
+
+    let related = RelatedWrits {
+      wids: self.compute_related_writs(writ, wid),
+      computed: unix_timestamp(),
+    };
+    if let Err(e) = self.related_writs.insert(wid, related.try_to_vec().unwrap()) {
+      if self.dev_mode {
+        println!("couldn't cache related writs - {}", e);
+      }
+    }
+    related.wids
+  }
+
+  // tags changed or the writ's gone, either way the list has to be worked out again
+  pub fn forget_related_writs(&self, wid: &[u8]) {
+    let _ = self.related_writs.remove(wid);
+  }
+
+  // a writ gained, lost or hid these tags, so every cached list built on them is stale now,
+  // which is cheaper to check for on the way out than to go and find all of them
+  pub fn touch_related_tags(&self, tags: &[String]) {
+    let now = IVec::from_i64(unix_timestamp());
+    for tag in tags.iter() {
+      let _ = self.tag_changes.insert(tag.as_bytes(), now.clone());
+    }
+  }
+
+  fn tag_changed_since(&self, tag: &str, when: i64) -> bool {
+    match self.tag_changes.get(tag.as_bytes()) {
+      Ok(Some(raw)) => raw.to_i64() >= when,
+      _ => false,
+    }
+  }
+
+  // whatever the viewer isn't allowed to see is skipped over
+  pub fn related_writs_for(&self, writ_id: &WritID, viewer: Option<u64>, amount: usize) -> Vec<RelatedWrit> {
+    let wid = writ_id.to_bin();
+    let writ = match self.writ_by_id_bytes(&wid) {
+      Some(writ) => writ,
+      None => return vec![],
+    };
+
+    self.related_writ_ids(&writ, &wid)
+      .iter()
+      .filter_map(|other| self.writ_by_id_bytes(other))
+      .filter_map(|other| {
+        let url = match self.writ_kind(&other.kind) {
+          Some(wk) => wk.slug_path(&other.slug),
+          None => format!("/{}/{}", other.kind, other.slug),
+        };
+        other.public(&viewer, false).map(|pw| RelatedWrit { url, writ: pw })
+      })
+      .take(amount)
+      .collect()
+  }
+}
+
+#[get("/writ/{id}/related")]
+pub async fn related_writs(
+  req: HttpRequest,
+  wid: web::Path<String>,
+  query: web::Query<RelatedQuery>,
+) -> HttpResponse {
+  let writ_id = match WritID::from_str(wid.as_str()) {
+    Some(writ_id) => writ_id,
+    None => return responses::BadRequest("bad writ id"),
+  };
+
+  let viewer = ORC.user_id_by_session(&req);
+  match ORC.writ_by_id_bytes(&writ_id.to_bin()).and_then(|writ| writ.public(&viewer, false)) {
+    Some(_) => {},
+    None => return responses::NotFound("no such writ"),
+  }
+
+  let amount = query.amount.unwrap_or(5).min(RELATED_CACHE_SIZE);
+  responses::Ok(ORC.related_writs_for(&writ_id, viewer, amount))
+}
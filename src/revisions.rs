@@ -0,0 +1,302 @@
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+use similar::{ChangeTag, TextDiff};
+use sled::{transaction::*, IVec};
+
+use crate::{
+  auth::User,
+  orchestrator::{Orchestrator, ORC},
+  responses,
+  utils::{unix_timestamp, FancyIVec},
+  writs::{RawWrit, Writ, WritError, WritID},
+};
+
+impl Orchestrator {
+  pub fn next_revision_version_in_transaction(
+    &self,
+    id_counter: &TransactionalTree,
+    writ_id: &WritID,
+  ) -> ConflictableTransactionResult<u64, ()> {
+    let key = revision_counter_key(writ_id);
+    let version = match id_counter.get(key.as_bytes())? {
+      Some(count) => count.to_u64() + 1,
+      None => 0,
+    };
+    id_counter.insert(key.as_bytes(), IVec::from_u64(version))?;
+    Ok(version)
+  }
+
+  pub fn writ_revision(&self, writ_id: &WritID, version: u64) -> Option<WritRevision> {
+    match self.writ_revisions.get(revision_key(&writ_id.to_bin(), version)) {
+      Ok(raw) => raw.map(|raw| WritRevision::try_from_slice(&raw).unwrap()),
+      Err(_) => None,
+    }
+  }
+
+  pub fn writ_revisions(&self, writ_id: &WritID) -> Vec<WritRevision> {
+    self.writ_revisions
+      .scan_prefix(writ_id.to_bin())
+      .values()
+      .filter_map(|res| res.ok())
+      .map(|raw| WritRevision::try_from_slice(&raw).unwrap())
+      .collect()
+  }
+
+  pub fn writ_revision_keys(&self, writ_id: &WritID) -> Vec<sled::IVec> {
+    self.writ_revisions
+      .scan_prefix(writ_id.to_bin())
+      .keys()
+      .filter_map(|res| res.ok())
+      .collect()
+  }
+
+  pub fn can_see_revisions(&self, usr: &User, writ_id: &WritID) -> bool {
+    self.can_edit_writ(usr.id, writ_id) || self.is_admin(usr.id)
+  }
+
+  // admins may look through anyone's history, but restoring commits as them,
+  // so it's left to the people who can edit the writ
+  pub fn can_restore_revisions(&self, usr: &User, writ_id: &WritID) -> bool {
+    self.can_edit_writ(usr.id, writ_id)
+  }
+
+  pub fn restore_writ_revision(&self, usr: &User, writ_id: &WritID, version: u64) -> Result<Writ, WritError> {
+    let writ = match self.writ_by_id(&writ_id.to_string()) {
+      Some(w) => w,
+      None => return Err(WritError::NonExistentID),
+    };
+
+    let revision = match self.writ_revision(writ_id, version) {
+      Some(rev) => rev,
+      None => return Err(WritError::NoSuchRevision),
+    };
+
+    RawWrit {
+      id: Some(writ.id.clone()),
+      title: revision.title,
+      raw_content: revision.raw_content,
+      kind: writ.kind.clone(),
+      tags: revision.tags,
+      public: writ.public,
+      commentable: Some(writ.commentable),
+      viewable_by: Some(writ.viewable_by.clone()),
+      is_md: Some(revision.is_md),
+      publish_at: writ.publish_at,
+      force_slug: None,
+      skip_sanitize: Some(writ.unsanitized && self.is_admin(usr.id)),
+      posted: None,
+    }.commit(usr.id)
+  }
+}
+
+#[inline]
+pub fn revision_key(wid: &[u8], version: u64) -> Vec<u8> {
+  let mut key = Vec::with_capacity(28);
+  key.extend_from_slice(wid);
+  key.extend_from_slice(&version.to_be_bytes());
+  key
+}
+
+#[inline]
+pub fn revision_counter_key(writ_id: &WritID) -> String {
+  format!("rev:{}", writ_id.to_string())
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct WritRevision {
+  pub version: u64,
+  pub title: String,
+  pub raw_content: String,
+  pub tags: Vec<String>,
+  pub is_md: bool,
+  pub when: i64,
+  pub editor: u64,
+}
+
+impl WritRevision {
+  pub fn new(version: u64, title: String, raw_content: String, tags: Vec<String>, is_md: bool, editor: u64) -> Self {
+    Self {
+      version,
+      title,
+      raw_content,
+      tags,
+      is_md,
+      when: unix_timestamp(),
+      editor,
+    }
+  }
+
+  pub fn summary(&self) -> RevisionSummary {
+    let editor_name = match ORC.user_by_id(self.editor) {
+      Some(usr) => usr.username,
+      None => "Unknown".to_string(),
+    };
+
+    RevisionSummary {
+      version: self.version,
+      title: self.title.clone(),
+      tags: self.tags.clone(),
+      when: self.when,
+      editor_id: self.editor,
+      editor_name,
+    }
+  }
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct RevisionSummary {
+  pub version: u64,
+  pub title: String,
+  pub tags: Vec<String>,
+  pub when: i64,
+  pub editor_id: u64,
+  pub editor_name: String,
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct DiffLine {
+  pub tag: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub old_line: Option<usize>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub new_line: Option<usize>,
+  pub text: String,
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct RevisionDiff {
+  pub from: RevisionSummary,
+  pub to: RevisionSummary,
+  pub lines: Vec<DiffLine>,
+}
+
+pub fn diff_revisions(from: &WritRevision, to: &WritRevision) -> RevisionDiff {
+  let diff = TextDiff::from_lines(from.raw_content.as_str(), to.raw_content.as_str());
+
+  let lines = diff.iter_all_changes()
+    .map(|change| DiffLine {
+      tag: match change.tag() {
+        ChangeTag::Equal => "equal",
+        ChangeTag::Delete => "delete",
+        ChangeTag::Insert => "insert",
+      }.to_string(),
+      old_line: change.old_index().map(|i| i + 1),
+      new_line: change.new_index().map(|i| i + 1),
+      text: change.value().trim_end_matches('\n').to_string(),
+    })
+    .collect();
+
+  RevisionDiff {
+    from: from.summary(),
+    to: to.summary(),
+    lines,
+  }
+}
+
+#[get("/writ/{id}/revisions")]
+pub async fn list_writ_revisions(
+  req: HttpRequest,
+  wid: web::Path<String>,
+) -> HttpResponse {
+  if let Some(usr) = ORC.user_by_session(&req) {
+    if let Some(writ_id) = WritID::from_str(wid.as_str()) {
+      if !ORC.can_see_revisions(&usr, &writ_id) {
+        return responses::Forbidden("only the writ's author may see its revisions");
+      }
+
+      let revisions = ORC.writ_revisions(&writ_id);
+      if revisions.len() == 0 {
+        return responses::NotFound("that writ has no revisions");
+      }
+
+      return responses::Ok(
+        revisions.iter()
+          .rev()
+          .map(|rev| rev.summary())
+          .collect::<Vec<RevisionSummary>>()
+      );
+    }
+    return responses::BadRequest("malformed writ id");
+  }
+
+  responses::Forbidden("You have to be logged in to see a writ's revisions")
+}
+
+#[get("/writ/{id}/revisions/{version}")]
+pub async fn get_writ_revision(
+  req: HttpRequest,
+  path: web::Path<(String, u64)>,
+) -> HttpResponse {
+  let (wid, version) = path.into_inner();
+  if let Some(usr) = ORC.user_by_session(&req) {
+    if let Some(writ_id) = WritID::from_str(&wid) {
+      if !ORC.can_see_revisions(&usr, &writ_id) {
+        return responses::Forbidden("only the writ's author may see its revisions");
+      }
+
+      return match ORC.writ_revision(&writ_id, version) {
+        Some(rev) => responses::Ok(rev),
+        None => responses::NotFound("no such revision"),
+      };
+    }
+    return responses::BadRequest("malformed writ id");
+  }
+
+  responses::Forbidden("You have to be logged in to see a writ's revisions")
+}
+
+#[get("/writ/{id}/revisions/{from}/diff/{to}")]
+pub async fn diff_writ_revisions(
+  req: HttpRequest,
+  path: web::Path<(String, u64, u64)>,
+) -> HttpResponse {
+  let (wid, from, to) = path.into_inner();
+  if let Some(usr) = ORC.user_by_session(&req) {
+    if let Some(writ_id) = WritID::from_str(&wid) {
+      if !ORC.can_see_revisions(&usr, &writ_id) {
+        return responses::Forbidden("only the writ's author may see its revisions");
+      }
+
+      let from = match ORC.writ_revision(&writ_id, from) {
+        Some(rev) => rev,
+        None => return responses::NotFound("the revision to diff from doesn't exist"),
+      };
+      let to = match ORC.writ_revision(&writ_id, to) {
+        Some(rev) => rev,
+        None => return responses::NotFound("the revision to diff to doesn't exist"),
+      };
+
+      return responses::Ok(diff_revisions(&from, &to));
+    }
+    return responses::BadRequest("malformed writ id");
+  }
+
+  responses::Forbidden("You have to be logged in to diff a writ's revisions")
+}
+
+#[post("/writ/{id}/revisions/{version}/restore")]
+pub async fn restore_writ_revision(
+  req: HttpRequest,
+  path: web::Path<(String, u64)>,
+) -> HttpResponse {
+  let (wid, version) = path.into_inner();
+  if let Some(usr) = ORC.user_by_session(&req) {
+    if let Some(writ_id) = WritID::from_str(&wid) {
+      if !ORC.user_has_some_attrs(usr.id, &["writer", "admin"]).unwrap_or(false) {
+        return responses::Forbidden("only authorized users may restore writ revisions");
+      }
+      if !ORC.can_restore_revisions(&usr, &writ_id) {
+        return responses::Forbidden("only the writ's author and editors may restore its revisions");
+      }
+
+      return match ORC.restore_writ_revision(&usr, &writ_id, version) {
+        Ok(w) => responses::Ok(w),
+        Err(e) => responses::BadRequest(format!("error: {}", e)),
+      };
+    }
+    return responses::BadRequest("malformed writ id");
+  }
+
+  responses::Forbidden("only authorized users may restore writ revisions")
+}
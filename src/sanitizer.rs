@@ -0,0 +1,150 @@
+use ammonia::Builder;
+use serde::{Deserialize, Serialize};
+
+use std::{
+  borrow::Cow,
+  collections::{HashMap, HashSet},
+  lazy::SyncLazy,
+};
+
+use super::CONF;
+
+// these get emptied out entirely rather than unwrapped, so they can never be allowed
+const CLEAN_CONTENT_TAGS: &[&str] = &["script", "style"];
+
+// all the highlighter needs, nothing that can move a box around or lay one over the page
+const STYLE_PROPERTIES: &[&str] = &["color", "background-color"];
+
+static SANITIZER: SyncLazy<Builder<'static>> = SyncLazy::new(|| {
+  let conf = CONF.read().sanitizer.clone();
+  conf.builder()
+});
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(default)]
+pub struct SanitizerConfig {
+  pub tags: Vec<String>,
+  pub generic_attributes: Vec<String>,
+  pub tag_attributes: HashMap<String, Vec<String>>,
+  pub url_schemes: Vec<String>,
+}
+
+impl Default for SanitizerConfig {
+  fn default() -> Self {
+    let strings = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+
+    let mut tag_attributes = HashMap::new();
+    for (tag, attrs) in [
+      ("a", &["href", "hreflang"][..]),
+      ("img", &["src", "alt", "width", "height", "srcset", "sizes", "loading"][..]),
+      ("source", &["src", "srcset", "sizes", "type", "media"][..]),
+      ("video", &["src", "poster", "controls", "loop", "muted", "preload", "width", "height"][..]),
+      ("audio", &["src", "controls", "loop", "muted", "preload"][..]),
+      ("input", &["type", "checked", "disabled"][..]),
+      ("ol", &["start", "reversed"][..]),
+      ("li", &["value"][..]),
+      ("td", &["colspan", "rowspan", "align"][..]),
+      ("th", &["colspan", "rowspan", "align", "scope"][..]),
+      // syntax highlighting is all inline styles, filter_style keeps just its colours
+      ("pre", &["style"][..]),
+      ("span", &["style"][..]),
+    ].iter() {
+      tag_attributes.insert(tag.to_string(), strings(attrs));
+    }
+
+    Self {
+      tags: strings(&[
+        "a", "abbr", "b", "blockquote", "br", "caption", "cite", "code", "dd", "del", "details",
+        "div", "dl", "dt", "em", "figcaption", "figure", "h1", "h2", "h3", "h4", "h5", "h6", "hr",
+        "i", "img", "input", "ins", "kbd", "li", "mark", "ol", "p", "picture", "pre", "q", "s",
+        "section", "small", "source", "span", "strong", "sub", "summary", "sup", "table", "tbody",
+        "td", "tfoot", "th", "thead", "tr", "u", "ul", "video", "audio",
+      ]),
+      generic_attributes: strings(&["id", "class", "title", "lang", "aria-hidden"]),
+      tag_attributes,
+      url_schemes: strings(&["http", "https", "mailto"]),
+    }
+  }
+}
+
+impl SanitizerConfig {
+  // the builder borrows its lists for good, the config is read once so leaking them is fine
+  fn builder(&self) -> Builder<'static> {
+    let leak = |s: &String| -> &'static str { Box::leak(s.clone().into_boxed_str()) };
+
+    let tags: HashSet<&'static str> = self.tags
+      .iter()
+      .filter(|tag| !CLEAN_CONTENT_TAGS.contains(&tag.as_str()))
+      .map(leak)
+      .collect();
+
+    let tag_attributes: HashMap<&'static str, HashSet<&'static str>> = self.tag_attributes
+      .iter()
+      .map(|(tag, attrs)| (leak(tag), attrs.iter().map(leak).collect()))
+      .collect();
+
+    let mut builder = Builder::default();
+    builder
+      .tags(tags)
+      .generic_attributes(self.generic_attributes.iter().map(leak).collect())
+      .tag_attributes(tag_attributes)
+      .url_schemes(self.url_schemes.iter().map(leak).collect())
+      .link_rel(Some("noopener noreferrer"))
+      .attribute_filter(|_, attr, value| match attr {
+        "style" => filter_style(value).map(Cow::Owned),
+        _ => Some(Cow::Borrowed(value)),
+      });
+    builder
+  }
+}
+
+// drops every declaration but plain colours, and the attribute with them if none are left
+pub fn filter_style(style: &str) -> Option<String> {
+  let kept: Vec<String> = style
+    .split(';')
+    .filter_map(|declaration| {
+      let (property, value) = declaration.split_once(':')?;
+      let property = property.trim().to_lowercase();
+      let value = value.trim();
+      let plain = !value.is_empty()
+        && value.chars().all(|c| c.is_ascii_alphanumeric() || "#(),.% ".contains(c))
+        && !value.to_lowercase().contains("url(");
+      (STYLE_PROPERTIES.contains(&property.as_str()) && plain).then(|| format!("{}:{};", property, value))
+    })
+    .collect();
+
+  if kept.is_empty() {
+    None
+  } else {
+    Some(kept.join(""))
+  }
+}
+
+pub fn sanitize_html(html: &str) -> String {
+  SANITIZER.clean(html).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn highlighter_colours_survive() {
+    assert_eq!(filter_style("color:#c0c5ce;"), Some("color:#c0c5ce;".to_string()));
+    assert_eq!(
+      filter_style("background-color: #2b303b; color: rgb(1, 2, 3)"),
+      Some("background-color:#2b303b;color:rgb(1, 2, 3);".to_string())
+    );
+  }
+
+  #[test]
+  fn layout_and_urls_are_dropped() {
+    assert_eq!(
+      filter_style("position:fixed;top:0;left:0;width:100%;height:100%;color:red"),
+      Some("color:red;".to_string())
+    );
+    assert_eq!(filter_style("background-color:url(https://evil.example/x.png)"), None);
+    assert_eq!(filter_style("z-index:9999"), None);
+    assert_eq!(filter_style(""), None);
+  }
+}
@@ -0,0 +1,336 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use regex::Regex;
+use sled::{transaction::*, IVec};
+
+use std::{collections::{BTreeMap, HashMap}, lazy::SyncLazy};
+
+use crate::orchestrator::{Orchestrator};
+use crate::utils::{unix_timestamp, FancyIVec};
+use crate::writs::Writ;
+
+static HTML_TAG_REGEX: SyncLazy<Regex> = SyncLazy::new(|| {
+  Regex::new(r"<[^>]*>").unwrap()
+});
+
+const STOP_WORDS: &[&str] = &[
+  "an", "and", "are", "as", "at", "be", "by", "for", "from", "in", "is", "it",
+  "of", "on", "or", "that", "the", "this", "to", "was", "with",
+];
+
+const TITLE_WEIGHT: u64 = 10;
+const TAG_WEIGHT: u64 = 6;
+const MAX_CONTENT_WEIGHT: u64 = 20;
+// how many postings a single search term may pull in before we stop looking
+const MAX_TERM_POSTINGS: usize = 5000;
+
+impl Orchestrator {
+  pub fn reindex_writ_search_terms_in_transaction(
+    &self,
+    search_index: &TransactionalTree,
+    search_terms: &TransactionalTree,
+    wid: &[u8],
+    terms: &BTreeMap<String, u64>,
+  ) -> ConflictableTransactionResult<(), ()> {
+    self.remove_writ_search_terms_in_transaction(search_index, search_terms, wid)?;
+
+    for (term, weight) in terms.iter() {
+      search_index.insert(search_key(term, wid), IVec::from_u64(*weight))?;
+    }
+
+    let indexed: Vec<String> = terms.keys().cloned().collect();
+    search_terms.insert(wid, indexed.try_to_vec().unwrap())?;
+    Ok(())
+  }
+
+  pub fn remove_writ_search_terms_in_transaction(
+    &self,
+    search_index: &TransactionalTree,
+    search_terms: &TransactionalTree,
+    wid: &[u8],
+  ) -> ConflictableTransactionResult<(), ()> {
+    if let Some(raw) = search_terms.remove(wid)? {
+      let old_terms: Vec<String> = BorshDeserialize::try_from_slice(&raw).unwrap();
+      for term in old_terms.iter() {
+        search_index.remove(search_key(term, wid))?;
+      }
+    }
+    Ok(())
+  }
+
+  // ranks writ ids by how well they match every term in the search,
+  // the last term is also matched as a prefix so half typed words still find things,
+  // also says whether any term had more postings than were looked at
+  pub fn search_writ_ids(&self, terms: &[String], kind: &str, author_id: Option<u64>) -> (Vec<IVec>, bool) {
+    let mut scores: HashMap<IVec, (f64, usize)> = HashMap::new();
+    let mut truncated = false;
+
+    for (i, term) in terms.iter().enumerate() {
+      let is_last = i == terms.len() - 1;
+      let mut prefix = term.as_bytes().to_vec();
+      if !is_last {
+        prefix.extend_from_slice(b":");
+      }
+
+      let mut term_scores: HashMap<IVec, f64> = HashMap::new();
+      let mut postings = 0;
+      let mut iter = self.search_index.scan_prefix(&prefix);
+      while let Some(Ok((key, raw_weight))) = iter.next() {
+        if postings == MAX_TERM_POSTINGS {
+          truncated = true;
+          break;
+        }
+        postings += 1;
+
+        if key.len() < 21 {
+          continue;
+        }
+        let split = key.len() - 21;
+        let wid = IVec::from(&key[split + 1..]);
+
+        if &wid[0..4] != kind.as_bytes() {
+          continue;
+        }
+        if let Some(author_id) = &author_id {
+          if wid[4..12] != author_id.to_be_bytes() {
+            continue;
+          }
+        }
+
+        let exact = key[..split] == *term.as_bytes();
+        let weight = raw_weight.to_u64() as f64;
+        let score = if exact { weight } else { weight / 2.0 };
+
+        let entry = term_scores.entry(wid).or_insert(0.0);
+        if score > *entry {
+          *entry = score;
+        }
+      }
+
+      // rarer terms say more about a writ than common ones
+      let rarity = 1.0 / (1.0 + (term_scores.len().max(1) as f64).ln());
+      for (wid, score) in term_scores.into_iter() {
+        let entry = scores.entry(wid).or_insert((0.0, 0));
+        entry.0 += score * rarity;
+        entry.1 += 1;
+      }
+    }
+
+    let mut ranked: Vec<(IVec, f64)> = scores.into_iter()
+      .filter(|(_, (_, matched))| *matched == terms.len())
+      .map(|(wid, (score, _))| (wid, score))
+      .collect();
+
+    ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+
+    (ranked.into_iter().map(|(wid, _)| wid).collect(), truncated)
+  }
+
+  pub fn writ_search_snippet(&self, writ: &Writ, terms: &[String]) -> Option<String> {
+    let wid = writ.writ_id()?.to_bin();
+    let text = if writ.is_md {
+      self.raw_content.get(&wid).ok()??.to_string()
+    } else {
+      strip_html(&self.content.get(&wid).ok()??.to_string())
+    };
+
+    highlighted_snippet(&text, terms)
+  }
+}
+
+const SEARCH_BACKFILL_MIGRATION: &[u8] = b"migration:search_index_backfill";
+
+// writs from before there was a search index never got their terms indexed,
+// so fill in whichever ones are missing from it
+pub fn backfill_search_index(
+  db: &sled::Db,
+  writs: &sled::Tree,
+  raw_content: &sled::Tree,
+  content: &sled::Tree,
+  search_index: &sled::Tree,
+  search_terms: &sled::Tree,
+) {
+  if db.contains_key(SEARCH_BACKFILL_MIGRATION).unwrap_or(false) {
+    return;
+  }
+
+  let mut indexed = 0;
+  for res in writs.iter() {
+    let (wid, raw) = res.expect("failed to read the writs tree while backfilling the search index");
+    if search_terms.contains_key(&wid).unwrap() {
+      continue;
+    }
+
+    let writ = Writ::try_from_slice(&raw).unwrap();
+    // only markdown writs keep their source, html ones are searched by what they render to
+    let body = if writ.is_md { raw_content } else { content };
+    let text = body.get(&wid).unwrap().map_or(String::new(), |raw| raw.to_string());
+    let terms = writ_search_terms(&writ.title, &writ.tags, &text, writ.is_md);
+
+    for (term, weight) in terms.iter() {
+      search_index.insert(search_key(term, &wid), IVec::from_u64(*weight)).unwrap();
+    }
+    let listed: Vec<String> = terms.keys().cloned().collect();
+    search_terms.insert(&wid, listed.try_to_vec().unwrap()).unwrap();
+    indexed += 1;
+  }
+
+  search_index.flush().unwrap();
+  search_terms.flush().unwrap();
+  db.insert(SEARCH_BACKFILL_MIGRATION, &unix_timestamp().to_be_bytes()).unwrap();
+  if indexed > 0 {
+    println!("added {} writs to the search index", indexed);
+  }
+}
+
+#[inline]
+pub fn search_key(term: &str, wid: &[u8]) -> Vec<u8> {
+  let mut key = Vec::with_capacity(term.len() + 21);
+  key.extend_from_slice(term.as_bytes());
+  key.extend_from_slice(b":");
+  key.extend_from_slice(wid);
+  key
+}
+
+pub fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+  text.split(|c: char| !c.is_alphanumeric())
+    .filter(|w| w.chars().count() >= 2 && w.len() <= 32)
+    .map(|w| w.to_lowercase())
+    .filter(|w| !STOP_WORDS.contains(&w.as_str()))
+}
+
+pub fn search_query_terms(search: &str) -> Vec<String> {
+  let mut terms: Vec<String> = vec![];
+  for term in tokenize(search) {
+    if !terms.contains(&term) {
+      terms.push(term);
+    }
+  }
+  terms
+}
+
+pub fn strip_html(html: &str) -> String {
+  HTML_TAG_REGEX.replace_all(html, " ").to_string()
+}
+
+pub fn writ_search_terms(title: &str, tags: &[String], raw_content: &str, is_md: bool) -> BTreeMap<String, u64> {
+  let mut terms: BTreeMap<String, u64> = BTreeMap::new();
+
+  let text = if is_md {
+    raw_content.to_string()
+  } else {
+    strip_html(raw_content)
+  };
+
+  for term in tokenize(&text) {
+    let weight = terms.entry(term).or_insert(0);
+    if *weight < MAX_CONTENT_WEIGHT {
+      *weight += 1;
+    }
+  }
+
+  for tag in tags.iter() {
+    for term in tokenize(tag) {
+      *terms.entry(term).or_insert(0) += TAG_WEIGHT;
+    }
+  }
+
+  for term in tokenize(title) {
+    *terms.entry(term).or_insert(0) += TITLE_WEIGHT;
+  }
+
+  terms
+}
+
+fn escape_html(text: &str) -> String {
+  text.replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+}
+
+fn word_matches(word: &str, terms: &[String]) -> bool {
+  let normalized = word.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase();
+  !normalized.is_empty() && terms.iter().any(|t| normalized.starts_with(t.as_str()))
+}
+
+// builds a short excerpt around the first matching word with matches wrapped in <mark>
+pub fn highlighted_snippet(text: &str, terms: &[String]) -> Option<String> {
+  let words: Vec<&str> = text.split_whitespace().collect();
+  let first = words.iter().position(|w| word_matches(w, terms))?;
+
+  let start = first.saturating_sub(12);
+  let end = (first + 24).min(words.len());
+
+  let mut snippet = String::new();
+  if start > 0 {
+    snippet.push_str("… ");
+  }
+
+  for (i, word) in words[start..end].iter().enumerate() {
+    if i > 0 {
+      snippet.push(' ');
+    }
+    if word_matches(word, terms) {
+      snippet.push_str("<mark>");
+      snippet.push_str(&escape_html(word));
+      snippet.push_str("</mark>");
+    } else {
+      snippet.push_str(&escape_html(word));
+    }
+  }
+
+  if end < words.len() {
+    snippet.push_str(" …");
+  }
+
+  Some(snippet)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn html_writ() -> Writ {
+    Writ {
+      id: "post:1:3".to_string(),
+      title: "Old notes".to_string(),
+      slug: "old-notes".to_string(),
+      kind: "post".to_string(),
+      tags: vec!["archive".to_string()],
+      posted: 1610427600,
+      public: true,
+      viewable_by: vec![],
+      commentable: true,
+      is_md: false,
+      publish_at: None,
+      toc: vec![],
+      word_count: 2,
+      reading_time: 1,
+      unsanitized: false,
+      content_hash: String::new(),
+      modified: 1610427600,
+    }
+  }
+
+  #[test]
+  fn backfill_indexes_the_body_of_html_writs() {
+    let db = sled::Config::new().temporary(true).open().unwrap();
+    let writs = db.open_tree("writs").unwrap();
+    let raw_content = db.open_tree("raw_content").unwrap();
+    let content = db.open_tree("content").unwrap();
+    let search_index = db.open_tree("search_index").unwrap();
+    let search_terms = db.open_tree("search_terms").unwrap();
+
+    let wid = b"post\x00\x00\x00\x00\x00\x00\x00\x01\x00\x00\x00\x00\x00\x00\x00\x03";
+    writs.insert(wid, html_writ().try_to_vec().unwrap()).unwrap();
+    content.insert(wid, "<p>Walrus <em>migrations</em></p>".as_bytes()).unwrap();
+
+    backfill_search_index(&db, &writs, &raw_content, &content, &search_index, &search_terms);
+
+    for term in ["walrus", "migrations", "old", "notes", "archive"].iter() {
+      assert!(search_index.contains_key(search_key(term, wid)).unwrap(), "{} wasn't indexed", term);
+    }
+    // the markup itself isn't something anyone searches for
+    assert!(!search_index.contains_key(search_key("em", wid)).unwrap());
+  }
+}
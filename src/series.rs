@@ -0,0 +1,400 @@
+use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse};
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+use sled::{transaction::*, IVec, Transactional};
+
+use crate::{
+  orchestrator::{Orchestrator, ORC},
+  responses,
+  utils::unix_timestamp,
+  writs::{Writ, WritID},
+};
+
+const MAX_SERIES_LENGTH: usize = 500;
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Series {
+  pub id: String, // {author_id}:{series_id}
+  pub name: String,
+  pub description: Option<String>,
+  pub writs: Vec<String>, // writ ids in reading order
+  pub created: i64,
+  pub updated: i64,
+}
+
+impl Series {
+  pub fn key(&self) -> Option<Vec<u8>> {
+    series_key_from_str(&self.id)
+  }
+
+  pub fn author_id(&self) -> Option<u64> {
+    self.id.split(':').next()?.parse().ok()
+  }
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct SeriesEntry {
+  pub id: String,
+  pub title: String,
+  pub url: String,
+  pub current: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct SeriesNav {
+  pub id: String,
+  pub name: String,
+  pub description: Option<String>,
+  pub position: usize,
+  pub total: usize,
+  pub prev: Option<SeriesEntry>,
+  pub next: Option<SeriesEntry>,
+  pub toc: Vec<SeriesEntry>,
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct RawSeries {
+  pub name: String,
+  pub description: Option<String>,
+  pub writs: Vec<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct SeriesOrder {
+  pub writs: Vec<String>,
+}
+
+impl Orchestrator {
+  pub fn series_by_id(&self, id: &str) -> Option<Series> {
+    let key = series_key_from_str(id)?;
+    match self.series.get(key) {
+      Ok(Some(raw)) => Some(Series::try_from_slice(&raw).unwrap()),
+      _ => None,
+    }
+  }
+
+  pub fn series_by_author(&self, author_id: u64) -> Vec<Series> {
+    self.series
+      .scan_prefix(author_id.to_be_bytes())
+      .values()
+      .filter_map(|res| res.ok())
+      .map(|raw| Series::try_from_slice(&raw).unwrap())
+      .collect()
+  }
+
+  // keys of every series a writ is part of
+  pub fn writ_series_keys(&self, wid: &[u8]) -> Vec<IVec> {
+    self.writ_series
+      .scan_prefix(wid)
+      .values()
+      .filter_map(|res| res.ok())
+      .collect()
+  }
+
+  pub fn series_of_writ(&self, wid: &[u8]) -> Vec<Series> {
+    self.writ_series_keys(wid)
+      .into_iter()
+      .filter_map(|key| self.series.get(key).ok()?)
+      .map(|raw| Series::try_from_slice(&raw).unwrap())
+      .collect()
+  }
+
+  // a series may only hold its author's own writs, once each
+  fn check_series_writs(&self, author_id: u64, writs: &[String]) -> bool {
+    if writs.len() > MAX_SERIES_LENGTH {
+      return false;
+    }
+    let mut seen: Vec<&String> = Vec::with_capacity(writs.len());
+    writs.iter().all(|id| {
+      if seen.contains(&id) {
+        return false;
+      }
+      seen.push(id);
+      match WritID::from_str(id) {
+        Some(wid) => wid.author == author_id && self.writs.contains_key(wid.to_bin()).unwrap_or(false),
+        None => false,
+      }
+    })
+  }
+
+  pub fn create_series(&self, author_id: u64, raw: RawSeries) -> Option<Series> {
+    let name = raw.name.trim().to_string();
+    if name.is_empty() || name.len() > 200 || !self.check_series_writs(author_id, &raw.writs) {
+      return None;
+    }
+
+    let series_id = self.generate_id(b"series").ok()?;
+    let now = unix_timestamp();
+    let series = Series {
+      id: format!("{}:{}", author_id, series_id),
+      name,
+      description: raw.description,
+      writs: vec![],
+      created: now,
+      updated: now,
+    };
+
+    self.set_series_writs(series, raw.writs)
+  }
+
+  // swaps a series' writ list for a new one, which covers adding, reordering and removing
+  pub fn set_series_writs(&self, mut series: Series, writs: Vec<String>) -> Option<Series> {
+    let key = series.key()?;
+    let old_writs = std::mem::replace(&mut series.writs, writs);
+    series.updated = unix_timestamp();
+
+    let res: TransactionResult<(), ()> = (&self.series, &self.writ_series)
+      .transaction(|(series_tree, writ_series)| {
+        for id in old_writs.iter() {
+          if let Some(wid) = WritID::from_str(id) {
+            writ_series.remove(writ_series_key(&wid.to_bin(), &key))?;
+          }
+        }
+        for id in series.writs.iter() {
+          if let Some(wid) = WritID::from_str(id) {
+            writ_series.insert(writ_series_key(&wid.to_bin(), &key), key.as_slice())?;
+          }
+        }
+        series_tree.insert(key.as_slice(), series.try_to_vec().unwrap())?;
+        Ok(())
+      });
+
+    res.ok().map(|_| series)
+  }
+
+  pub fn remove_series(&self, series: &Series) -> bool {
+    let key = match series.key() {
+      Some(key) => key,
+      None => return false,
+    };
+
+    let res: TransactionResult<(), ()> = (&self.series, &self.writ_series)
+      .transaction(|(series_tree, writ_series)| {
+        for id in series.writs.iter() {
+          if let Some(wid) = WritID::from_str(id) {
+            writ_series.remove(writ_series_key(&wid.to_bin(), &key))?;
+          }
+        }
+        series_tree.remove(key.as_slice())?;
+        Ok(())
+      });
+    res.is_ok()
+  }
+
+  // drops a writ that's going away out of every series that had it
+  pub fn remove_writ_from_series_in_transaction(
+    &self,
+    series_tree: &TransactionalTree,
+    writ_series: &TransactionalTree,
+    writ: &Writ,
+    wid: &[u8],
+    series_keys: &[IVec],
+  ) -> ConflictableTransactionResult<(), ()> {
+    for key in series_keys.iter() {
+      writ_series.remove(writ_series_key(wid, key))?;
+      if let Some(raw) = series_tree.get(key)? {
+        let mut series = Series::try_from_slice(&raw).unwrap();
+        series.writs.retain(|id| *id != writ.id);
+        series_tree.insert(key, series.try_to_vec().unwrap())?;
+      }
+    }
+    Ok(())
+  }
+
+  // a series with only the writs a viewer is allowed to see left in it
+  pub fn series_as_seen_by(&self, mut series: Series, viewer: Option<u64>) -> Series {
+    series.writs.retain(|id| match self.writ_by_id(id) {
+      Some(writ) => writ.is_visible_to(&viewer),
+      None => false,
+    });
+    series
+  }
+
+  // previous/next and contents for a series as whoever's reading the writ gets to see it
+  pub fn series_nav(&self, series: &Series, current: &str, viewer: Option<u64>) -> Option<SeriesNav> {
+    let toc: Vec<SeriesEntry> = series.writs
+      .iter()
+      .filter_map(|id| self.writ_by_id(id))
+      .filter(|writ| writ.is_visible_to(&viewer))
+      .map(|writ| SeriesEntry {
+        url: match self.writ_kind(&writ.kind) {
+          Some(wk) => wk.slug_path(&writ.slug),
+          None => format!("/{}/{}", writ.kind, writ.slug),
+        },
+        current: writ.id == current,
+        id: writ.id,
+        title: writ.title,
+      })
+      .collect();
+
+    let position = toc.iter().position(|entry| entry.current)?;
+
+    Some(SeriesNav {
+      id: series.id.clone(),
+      name: series.name.clone(),
+      description: series.description.clone(),
+      position: position + 1,
+      total: toc.len(),
+      prev: position.checked_sub(1).and_then(|i| toc.get(i)).cloned(),
+      next: toc.get(position + 1).cloned(),
+      toc,
+    })
+  }
+}
+
+pub fn series_key_from_str(id: &str) -> Option<Vec<u8>> {
+  let mut parts = id.split(':');
+  let author_id: u64 = parts.next()?.parse().ok()?;
+  let series_id: u64 = parts.next()?.parse().ok()?;
+  if parts.next().is_some() {
+    return None;
+  }
+  Some(series_key(author_id, series_id))
+}
+
+#[inline]
+pub fn series_key(author_id: u64, series_id: u64) -> Vec<u8> {
+  let mut key = Vec::with_capacity(16);
+  key.extend_from_slice(&author_id.to_be_bytes());
+  key.extend_from_slice(&series_id.to_be_bytes());
+  key
+}
+
+#[inline]
+pub fn writ_series_key(wid: &[u8], series_key: &[u8]) -> Vec<u8> {
+  let mut key = Vec::with_capacity(wid.len() + series_key.len());
+  key.extend_from_slice(wid);
+  key.extend_from_slice(series_key);
+  key
+}
+
+#[get("/series/{id}")]
+pub async fn get_series(
+  req: HttpRequest,
+  id: web::Path<String>,
+) -> HttpResponse {
+  let viewer = ORC.user_id_by_session(&req);
+  if let Some(series) = ORC.series_by_id(&id) {
+    return responses::Ok(ORC.series_as_seen_by(series, viewer));
+  }
+  responses::NotFound("no such series")
+}
+
+#[get("/series/by/{author_id}")]
+pub async fn list_author_series(
+  req: HttpRequest,
+  author_id: web::Path<u64>,
+) -> HttpResponse {
+  let viewer = ORC.user_id_by_session(&req);
+  responses::Ok(
+    ORC.series_by_author(author_id.into_inner())
+      .into_iter()
+      .map(|series| ORC.series_as_seen_by(series, viewer))
+      .collect::<Vec<Series>>()
+  )
+}
+
+#[put("/series")]
+pub async fn create_series(
+  req: HttpRequest,
+  raw: web::Json<RawSeries>,
+) -> HttpResponse {
+  if let Some(usr_id) = ORC.user_id_by_session(&req) {
+    if !ORC.user_has_some_attrs(usr_id, &["writer", "admin"]).unwrap_or(false) {
+      return responses::Forbidden("only writers may make series");
+    }
+
+    return match ORC.create_series(usr_id, raw.into_inner()) {
+      Some(series) => responses::Ok(series),
+      None => responses::BadRequest(
+        "a series needs a name and may only hold your own writs, each of them once",
+      ),
+    };
+  }
+
+  responses::Forbidden("you have to be logged in to make a series")
+}
+
+#[post("/series/{id}/writs")]
+pub async fn reorder_series(
+  req: HttpRequest,
+  id: web::Path<String>,
+  order: web::Json<SeriesOrder>,
+) -> HttpResponse {
+  if let Some(usr_id) = ORC.user_id_by_session(&req) {
+    let series = match ORC.series_by_id(&id) {
+      Some(series) => series,
+      None => return responses::NotFound("no such series"),
+    };
+
+    if series.author_id() != Some(usr_id) {
+      return responses::Forbidden("only the series' author may change it");
+    }
+
+    let writs = order.into_inner().writs;
+    if !ORC.check_series_writs(usr_id, &writs) {
+      return responses::BadRequest("a series may only hold your own writs, each of them once");
+    }
+
+    return match ORC.set_series_writs(series, writs) {
+      Some(series) => responses::Ok(series),
+      None => responses::InternalServerError("couldn't update the series"),
+    };
+  }
+
+  responses::Forbidden("you have to be logged in to change a series")
+}
+
+#[delete("/series/{id}/writ/{writ_id}")]
+pub async fn remove_writ_from_series(
+  req: HttpRequest,
+  path: web::Path<(String, String)>,
+) -> HttpResponse {
+  let (id, writ_id) = path.into_inner();
+  if let Some(usr_id) = ORC.user_id_by_session(&req) {
+    let series = match ORC.series_by_id(&id) {
+      Some(series) => series,
+      None => return responses::NotFound("no such series"),
+    };
+
+    if series.author_id() != Some(usr_id) {
+      return responses::Forbidden("only the series' author may change it");
+    }
+
+    if !series.writs.contains(&writ_id) {
+      return responses::NotFound("that writ isn't part of the series");
+    }
+
+    let writs: Vec<String> = series.writs.iter().filter(|w| **w != writ_id).cloned().collect();
+    return match ORC.set_series_writs(series, writs) {
+      Some(series) => responses::Ok(series),
+      None => responses::InternalServerError("couldn't update the series"),
+    };
+  }
+
+  responses::Forbidden("you have to be logged in to change a series")
+}
+
+#[delete("/series/{id}")]
+pub async fn delete_series(
+  req: HttpRequest,
+  id: web::Path<String>,
+) -> HttpResponse {
+  if let Some(usr_id) = ORC.user_id_by_session(&req) {
+    let series = match ORC.series_by_id(&id) {
+      Some(series) => series,
+      None => return responses::NotFound("no such series"),
+    };
+
+    if series.author_id() != Some(usr_id) && !ORC.is_admin(usr_id) {
+      return responses::Forbidden("only the series' author may remove it");
+    }
+
+    return match ORC.remove_series(&series) {
+      true => responses::Accepted("series removed"),
+      false => responses::InternalServerError("couldn't remove the series"),
+    };
+  }
+
+  responses::Forbidden("you have to be logged in to remove a series")
+}
@@ -0,0 +1,284 @@
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse};
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+use sled::{transaction::*, Transactional};
+use time::Duration;
+
+use std::collections::BTreeMap;
+
+use crate::{
+  expirable_data::ExpirableData,
+  orchestrator::{Orchestrator, ORC},
+  responses,
+  utils::{random_string, unix_timestamp},
+  writs::{PublicWrit, WritID},
+};
+
+const SHARE_TOKEN_LENGTH: usize = 32;
+const MIN_SHARE_LIFETIME: i64 = 60;
+const MAX_SHARE_LIFETIME: i64 = 60 * 60 * 24 * 365;
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, PartialEq, Debug)]
+pub struct ShareLink {
+  pub wid: Vec<u8>,
+  pub created_by: u64,
+  pub created: i64,
+  pub expires: Option<i64>,
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct PublicShareLink {
+  pub token: String,
+  pub url: String,
+  pub created_by: u64,
+  pub created: i64,
+  pub expires: Option<i64>,
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct ShareLinkRequest {
+  pub expires_in: Option<i64>, // seconds, links without one last until they're revoked
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct ShareQuery {
+  pub share: Option<String>,
+}
+
+impl Orchestrator {
+  pub fn create_share_link(&self, writ_id: &WritID, created_by: u64, expires_in: Option<i64>) -> Option<PublicShareLink> {
+    let wid = writ_id.to_bin();
+    let now = unix_timestamp();
+    let link = ShareLink {
+      wid: wid.clone(),
+      created_by,
+      created: now,
+      expires: expires_in.map(|secs| now + secs),
+    };
+
+    let res: TransactionResult<String, ()> = (&self.writs, &self.share_links, &self.writ_share_links)
+      .transaction(|(writs, share_links, writ_share_links)| {
+        if writs.get(&wid)?.is_none() {
+          return Err(ConflictableTransactionError::Abort(()));
+        }
+        let token = random_string(SHARE_TOKEN_LENGTH);
+        share_links.insert(token.as_bytes(), link.try_to_vec().unwrap())?;
+        writ_share_links.insert(writ_share_key(&wid, &token), &[] as &[u8])?;
+        Ok(token)
+      });
+
+    let token = res.ok()?;
+    if let Some(secs) = expires_in {
+      let mut map = BTreeMap::new();
+      map.insert("share_links".to_string(), vec![token.as_bytes().to_vec()]);
+      map.insert("writ_share_links".to_string(), vec![writ_share_key(&wid, &token)]);
+      self.expire_data(secs, ExpirableData::MultiTree(map), Some(token.as_bytes()));
+    }
+
+    Some(self.public_share_link(token, link))
+  }
+
+  // expiry is swept once a second, the timestamp check covers the gap
+  pub fn share_link(&self, token: &str) -> Option<ShareLink> {
+    let link = match self.share_links.get(token.as_bytes()) {
+      Ok(Some(raw)) => ShareLink::try_from_slice(&raw).unwrap(),
+      _ => return None,
+    };
+    match link.expires {
+      Some(expires) if expires <= unix_timestamp() => None,
+      _ => Some(link),
+    }
+  }
+
+  pub fn share_link_grants(&self, token: &str, writ_id: &WritID) -> bool {
+    self.share_link(token).map_or(false, |link| link.wid == writ_id.to_bin())
+  }
+
+  // the token stands in for the author, so the writ is shown as they'd see it
+  pub fn shared_public_writ(&self, token: &str, writ_id: &WritID) -> Option<PublicWrit> {
+    if !self.share_link_grants(token, writ_id) {
+      return None;
+    }
+    self.writ_by_id_bytes(&writ_id.to_bin())?.public(&Some(writ_id.author), true)
+  }
+
+  pub fn writ_share_links(&self, writ_id: &WritID) -> Vec<PublicShareLink> {
+    let wid = writ_id.to_bin();
+    self.writ_share_links
+      .scan_prefix(&wid)
+      .keys()
+      .filter_map(|res| res.ok())
+      .filter_map(|key| String::from_utf8(key[wid.len()..].to_vec()).ok())
+      .filter_map(|token| {
+        let link = self.share_link(&token)?;
+        Some(self.public_share_link(token, link))
+      })
+      .collect()
+  }
+
+  pub fn revoke_share_link(&self, writ_id: &WritID, token: &str) -> bool {
+    let wid = writ_id.to_bin();
+    let res: TransactionResult<ShareLink, ()> = (&self.share_links, &self.writ_share_links)
+      .transaction(|(share_links, writ_share_links)| {
+        let link = match share_links.get(token.as_bytes())? {
+          Some(raw) => ShareLink::try_from_slice(&raw).unwrap(),
+          None => return Err(ConflictableTransactionError::Abort(())),
+        };
+        if link.wid != wid {
+          return Err(ConflictableTransactionError::Abort(()));
+        }
+        share_links.remove(token.as_bytes())?;
+        writ_share_links.remove(writ_share_key(&wid, token))?;
+        Ok(link)
+      });
+
+    match res {
+      Ok(link) => {
+        if link.expires.is_some() {
+          self.unexpire_data(token.as_bytes());
+        }
+        true
+      },
+      Err(_) => false,
+    }
+  }
+
+  pub fn writ_share_keys(&self, wid: &[u8]) -> Vec<Vec<u8>> {
+    self.writ_share_links
+      .scan_prefix(wid)
+      .keys()
+      .filter_map(|res| res.ok())
+      .map(|key| key.to_vec())
+      .collect()
+  }
+
+  pub fn remove_writ_share_links_in_transaction(
+    &self,
+    share_links: &TransactionalTree,
+    writ_share_links: &TransactionalTree,
+    wid: &[u8],
+    share_keys: &[Vec<u8>],
+  ) -> ConflictableTransactionResult<(), ()> {
+    for key in share_keys.iter() {
+      writ_share_links.remove(key.as_slice())?;
+      share_links.remove(&key[wid.len()..])?;
+    }
+    Ok(())
+  }
+
+  fn public_share_link(&self, token: String, link: ShareLink) -> PublicShareLink {
+    let writ_id = WritID::from_bin(&link.wid);
+    let kind = String::from_utf8_lossy(&writ_id.kind).to_string();
+    let path = match self.writ_kind(&kind) {
+      Some(wk) => wk.id_path(writ_id.author, writ_id.id),
+      None => format!("/{}/{}:{}", kind, writ_id.author, writ_id.id),
+    };
+    PublicShareLink {
+      url: format!("{}?share={}", path, token),
+      token,
+      created_by: link.created_by,
+      created: link.created,
+      expires: link.expires,
+    }
+  }
+}
+
+#[inline]
+pub fn writ_share_key(wid: &[u8], token: &str) -> Vec<u8> {
+  let mut key = Vec::with_capacity(wid.len() + token.len());
+  key.extend_from_slice(wid);
+  key.extend_from_slice(token.as_bytes());
+  key
+}
+
+#[post("/writ/{id}/share-links")]
+pub async fn create_share_link(
+  req: HttpRequest,
+  wid: web::Path<String>,
+  slr: web::Json<ShareLinkRequest>,
+) -> HttpResponse {
+  let usr_id = match ORC.user_id_by_session(&req) {
+    Some(id) => id,
+    None => return responses::Forbidden("you have to be logged in to share writs"),
+  };
+
+  let writ_id = match WritID::from_str(wid.as_str()) {
+    Some(writ_id) => writ_id,
+    None => return responses::BadRequest("bad writ id"),
+  };
+
+  if !ORC.can_edit_writ(usr_id, &writ_id) && !ORC.is_admin(usr_id) {
+    return responses::Forbidden("only the writ's author and collaborators may share it");
+  }
+
+  if let Some(secs) = slr.expires_in {
+    if secs < MIN_SHARE_LIFETIME || secs > MAX_SHARE_LIFETIME {
+      return responses::BadRequest("share links have to last between a minute and a year");
+    }
+  }
+
+  let hitter = format!("shrl{}", usr_id);
+  if let Some(rl) = ORC.ratelimiter
+    .hit(hitter.as_bytes(), 30, Duration::minutes(10))
+  {
+    if rl.is_timing_out() {
+      return responses::TooManyRequests(format!(
+        "too many share links, timeout has {} minutes left.",
+        rl.minutes_left()
+      ));
+    }
+  }
+
+  match ORC.create_share_link(&writ_id, usr_id, slr.expires_in) {
+    Some(link) => responses::Ok(link),
+    None => responses::NotFound("couldn't make a share link, does the writ exist?"),
+  }
+}
+
+#[get("/writ/{id}/share-links")]
+pub async fn list_share_links(
+  req: HttpRequest,
+  wid: web::Path<String>,
+) -> HttpResponse {
+  let usr_id = match ORC.user_id_by_session(&req) {
+    Some(id) => id,
+    None => return responses::Forbidden("you have to be logged in to see a writ's share links"),
+  };
+
+  let writ_id = match WritID::from_str(wid.as_str()) {
+    Some(writ_id) => writ_id,
+    None => return responses::BadRequest("bad writ id"),
+  };
+
+  if !ORC.can_edit_writ(usr_id, &writ_id) && !ORC.is_admin(usr_id) {
+    return responses::Forbidden("only the writ's author and collaborators may see its share links");
+  }
+
+  responses::Ok(ORC.writ_share_links(&writ_id))
+}
+
+#[delete("/writ/{id}/share-links/{token}")]
+pub async fn revoke_share_link(
+  req: HttpRequest,
+  path: web::Path<(String, String)>,
+) -> HttpResponse {
+  let usr_id = match ORC.user_id_by_session(&req) {
+    Some(id) => id,
+    None => return responses::Forbidden("you have to be logged in to revoke share links"),
+  };
+
+  let (wid, token) = path.into_inner();
+  let writ_id = match WritID::from_str(&wid) {
+    Some(writ_id) => writ_id,
+    None => return responses::BadRequest("bad writ id"),
+  };
+
+  if !ORC.can_edit_writ(usr_id, &writ_id) && !ORC.is_admin(usr_id) {
+    return responses::Forbidden("only the writ's author and collaborators may revoke its share links");
+  }
+
+  if ORC.revoke_share_link(&writ_id, &token) {
+    return responses::Accepted("share link revoked");
+  }
+  responses::NotFound("no such share link on that writ")
+}
@@ -0,0 +1,217 @@
+use actix_web::{get, http::header, web, HttpResponse};
+use parking_lot::RwLock;
+
+use std::{
+  lazy::SyncLazy,
+  sync::atomic::{AtomicBool, Ordering},
+  time::UNIX_EPOCH,
+};
+
+use super::CONF;
+use crate::{
+  orchestrator::{Orchestrator, ORC},
+  utils::datetime_from_unix_timestamp,
+};
+
+// well under the 50k the protocol allows, crawlers fetch smaller pages more happily
+const SITEMAP_PAGE_SIZE: usize = 10_000;
+
+// partials, error pages and whatever is only reached through a link somebody got mailed
+const UNLISTED_TEMPLATES: &[&str] = &["404.html", "footer.html", "head-imports.html", "old-index.html"];
+const UNLISTED_TEMPLATE_PREFIXES: &[&str] = &["magic-link", "admin"];
+
+const DISALLOWED_PATHS: &[&str] = &["/404.html", "/admin.html", "/admin"];
+
+static SITEMAP: SyncLazy<RwLock<Option<Vec<SitemapEntry>>>> = SyncLazy::new(|| RwLock::new(None));
+static SITEMAP_STALE: AtomicBool = AtomicBool::new(true);
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct SitemapEntry {
+  pub path: String,
+  pub lastmod: Option<i64>,
+}
+
+impl Orchestrator {
+  // every public writ goes in under its current slug
+  pub fn sitemap_writ_entries(&self) -> Vec<SitemapEntry> {
+    let kinds = self.writ_kinds();
+    let mut entries: Vec<SitemapEntry> = self.slugs
+      .iter()
+      .filter_map(|res| res.ok())
+      .filter_map(|(_, wid)| {
+        let writ = self.writ_by_id_bytes(&wid)?;
+        if !writ.public {
+          return None;
+        }
+        let wk = kinds.iter().find(|wk| wk.kind == writ.kind)?;
+        Some(SitemapEntry {
+          path: wk.slug_path(&writ.slug),
+          lastmod: Some(writ.modified.max(writ.posted)),
+        })
+      })
+      .collect();
+
+    entries.sort_by(|a, b| b.lastmod.cmp(&a.lastmod));
+    entries
+  }
+}
+
+// the pages serve_files_and_templates renders for anyone
+pub fn sitemap_template_entries() -> Vec<SitemapEntry> {
+  let kind_templates: Vec<String> = ORC.writ_kinds().into_iter().map(|wk| wk.template).collect();
+
+  let mut entries = vec![SitemapEntry { path: "/".to_string(), lastmod: template_modified("index.html") }];
+  let mut names: Vec<String> = match std::fs::read_dir("./templates") {
+    Ok(dir) => dir
+      .filter_map(|res| res.ok())
+      .filter_map(|entry| entry.file_name().into_string().ok())
+      .filter(|name| name.ends_with(".html") && name != "index.html")
+      .filter(|name| !UNLISTED_TEMPLATES.contains(&name.as_str()) && !kind_templates.contains(name))
+      .filter(|name| !UNLISTED_TEMPLATE_PREFIXES.iter().any(|prefix| name.starts_with(prefix)))
+      .collect(),
+    Err(_) => vec![],
+  };
+  names.sort();
+
+  for name in names {
+    entries.push(SitemapEntry {
+      path: format!("/{}", name.trim_end_matches(".html")),
+      lastmod: template_modified(&name),
+    });
+  }
+  entries
+}
+
+fn template_modified(name: &str) -> Option<i64> {
+  let modified = std::fs::metadata(format!("./templates/{}", name)).ok()?.modified().ok()?;
+  Some(modified.duration_since(UNIX_EPOCH).ok()?.as_secs() as i64)
+}
+
+// writs come and go all the time, so the sitemap only gets rebuilt when somebody asks for it
+pub fn invalidate_sitemap() {
+  SITEMAP_STALE.store(true, Ordering::Release);
+}
+
+fn sitemap_entries() -> Vec<SitemapEntry> {
+  if SITEMAP_STALE.load(Ordering::Acquire) {
+    let mut sitemap = SITEMAP.write();
+    // somebody else may have rebuilt it while this was waiting on the lock
+    if SITEMAP_STALE.swap(false, Ordering::AcqRel) || sitemap.is_none() {
+      let mut entries = sitemap_template_entries();
+      entries.extend(ORC.sitemap_writ_entries());
+      *sitemap = Some(entries);
+    }
+  }
+  SITEMAP.read().clone().unwrap_or_default()
+}
+
+fn xml_escape(text: &str) -> String {
+  text
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+    .replace('\'', "&apos;")
+}
+
+fn w3c_date(timestamp: i64) -> String {
+  datetime_from_unix_timestamp(timestamp).format(time::Format::Rfc3339)
+}
+
+fn render_urlset(domain: &str, entries: &[SitemapEntry]) -> String {
+  let mut xml = String::from(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+  );
+  for entry in entries.iter() {
+    xml.push_str("  <url>\n");
+    xml.push_str(&format!("    <loc>{}</loc>\n", xml_escape(&format!("https://{}{}", domain, entry.path))));
+    if let Some(lastmod) = entry.lastmod {
+      xml.push_str(&format!("    <lastmod>{}</lastmod>\n", w3c_date(lastmod)));
+    }
+    xml.push_str("  </url>\n");
+  }
+  xml.push_str("</urlset>\n");
+  xml
+}
+
+fn render_sitemap_index(domain: &str, entries: &[SitemapEntry]) -> String {
+  let mut xml = String::from(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<sitemapindex xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+  );
+  for (i, page) in entries.chunks(SITEMAP_PAGE_SIZE).enumerate() {
+    xml.push_str("  <sitemap>\n");
+    xml.push_str(&format!("    <loc>https://{}/sitemap-{}.xml</loc>\n", xml_escape(domain), i + 1));
+    if let Some(lastmod) = page.iter().filter_map(|e| e.lastmod).max() {
+      xml.push_str(&format!("    <lastmod>{}</lastmod>\n", w3c_date(lastmod)));
+    }
+    xml.push_str("  </sitemap>\n");
+  }
+  xml.push_str("</sitemapindex>\n");
+  xml
+}
+
+fn xml_response(body: String) -> HttpResponse {
+  HttpResponse::Ok()
+    .content_type("application/xml; charset=utf-8")
+    .append_header((header::CACHE_CONTROL, "public, max-age=3600"))
+    .body(body)
+}
+
+// small sites get one urlset, past a page's worth it turns into an index of pages
+#[get("/sitemap.xml")]
+pub async fn sitemap() -> HttpResponse {
+  let domain = CONF.read().domain.clone();
+  let entries = sitemap_entries();
+  if entries.len() <= SITEMAP_PAGE_SIZE {
+    return xml_response(render_urlset(&domain, &entries));
+  }
+  xml_response(render_sitemap_index(&domain, &entries))
+}
+
+#[get("/sitemap-{page:\\d+}.xml")]
+pub async fn sitemap_page(page: web::Path<usize>) -> HttpResponse {
+  let domain = CONF.read().domain.clone();
+  let entries = sitemap_entries();
+  match entries.chunks(SITEMAP_PAGE_SIZE).nth(page.into_inner().wrapping_sub(1)) {
+    Some(chunk) => xml_response(render_urlset(&domain, chunk)),
+    None => HttpResponse::NotFound()
+      .content_type("text/plain")
+      .body("there's no sitemap page with that number"),
+  }
+}
+
+#[get("/robots.txt")]
+pub async fn robots_txt() -> HttpResponse {
+  let mut robots = String::from("User-agent: *\n");
+  for path in DISALLOWED_PATHS.iter() {
+    robots.push_str(&format!("Disallow: {}\n", path));
+  }
+  // share links open private writs, those must never end up in an index
+  robots.push_str("Disallow: /*?share=\n");
+  robots.push_str(&format!("\nSitemap: https://{}/sitemap.xml\n", CONF.read().domain));
+
+  HttpResponse::Ok()
+    .content_type("text/plain; charset=utf-8")
+    .append_header((header::CACHE_CONTROL, "public, max-age=86400"))
+    .body(robots)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn xml_escape_covers_all_five_entities() {
+    assert_eq!(
+      xml_escape(r#"<a href="/post/x?a=1&b=2">Tom's</a>"#),
+      "&lt;a href=&quot;/post/x?a=1&amp;b=2&quot;&gt;Tom&apos;s&lt;/a&gt;"
+    );
+  }
+
+  #[test]
+  fn xml_escape_treats_entities_as_text() {
+    // titles that already look escaped are still just text to us
+    assert_eq!(xml_escape("&lt;"), "&amp;lt;");
+    assert_eq!(xml_escape("plain/path-1"), "plain/path-1");
+  }
+}
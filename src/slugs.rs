@@ -0,0 +1,105 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+use sled::{transaction::*, IVec};
+
+use crate::orchestrator::Orchestrator;
+use crate::utils::unix_timestamp;
+use crate::writs::Writ;
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct RetiredSlug {
+  pub kind: String,
+  pub slug: String,
+  pub title: String,
+  pub retired: i64,
+}
+
+impl RetiredSlug {
+  pub fn slug_key(&self) -> String {
+    format!("{}:{}", self.kind, self.slug)
+  }
+}
+
+impl Orchestrator {
+  // which writ used to go by this slug, if any
+  pub fn retired_slug_owner(&self, kind: &str, slug: &str) -> Option<IVec> {
+    self.retired_slugs.get(format!("{}:{}", kind, slug).as_bytes()).ok()?
+  }
+
+  pub fn slug_history(&self, wid: &[u8]) -> Vec<RetiredSlug> {
+    match self.slug_history.get(wid) {
+      Ok(Some(raw)) => BorshDeserialize::try_from_slice(&raw).unwrap(),
+      _ => vec![],
+    }
+  }
+
+  pub fn retire_slug_in_transaction(
+    &self,
+    retired_slugs: &TransactionalTree,
+    slug_history: &TransactionalTree,
+    wid: &[u8],
+    old_writ: &Writ,
+  ) -> ConflictableTransactionResult<(), ()> {
+    let mut history: Vec<RetiredSlug> = match slug_history.get(wid)? {
+      Some(raw) => BorshDeserialize::try_from_slice(&raw).unwrap(),
+      None => vec![],
+    };
+
+    history.retain(|rs| rs.kind != old_writ.kind || rs.slug != old_writ.slug);
+    history.push(RetiredSlug {
+      kind: old_writ.kind.clone(),
+      slug: old_writ.slug.clone(),
+      title: old_writ.title.clone(),
+      retired: unix_timestamp(),
+    });
+
+    retired_slugs.insert(old_writ.slug_key().as_bytes(), wid)?;
+    slug_history.insert(wid, history.try_to_vec().unwrap())?;
+    Ok(())
+  }
+
+  // a writ taking a slug back, or someone forcing one, means it stops redirecting
+  pub fn reclaim_slug_in_transaction(
+    &self,
+    retired_slugs: &TransactionalTree,
+    slug_history: &TransactionalTree,
+    wid: &[u8],
+    writ: &Writ,
+  ) -> ConflictableTransactionResult<(), ()> {
+    let slug_key = writ.slug_key();
+    if let Some(owner) = retired_slugs.remove(slug_key.as_bytes())? {
+      if let Some(raw) = slug_history.get(&owner)? {
+        let mut history: Vec<RetiredSlug> = BorshDeserialize::try_from_slice(&raw).unwrap();
+        history.retain(|rs| rs.slug_key() != slug_key);
+        slug_history.insert(&owner, history.try_to_vec().unwrap())?;
+      }
+    }
+
+    if let Some(raw) = slug_history.get(wid)? {
+      let mut history: Vec<RetiredSlug> = BorshDeserialize::try_from_slice(&raw).unwrap();
+      history.retain(|rs| rs.slug_key() != slug_key);
+      slug_history.insert(wid, history.try_to_vec().unwrap())?;
+    }
+    Ok(())
+  }
+
+  pub fn remove_slug_history_in_transaction(
+    &self,
+    retired_slugs: &TransactionalTree,
+    slug_history: &TransactionalTree,
+    wid: &[u8],
+  ) -> ConflictableTransactionResult<(), ()> {
+    if let Some(raw) = slug_history.remove(wid)? {
+      let history: Vec<RetiredSlug> = BorshDeserialize::try_from_slice(&raw).unwrap();
+      for rs in history.iter() {
+        let slug_key = rs.slug_key();
+        if let Some(owner) = retired_slugs.get(slug_key.as_bytes())? {
+          if owner.as_ref() == wid {
+            retired_slugs.remove(slug_key.as_bytes())?;
+          }
+        }
+      }
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,355 @@
+use actix_web::{get, web, HttpResponse};
+use borsh::{BorshDeserialize, BorshSerialize};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use sled::{transaction::*, IVec};
+
+use std::collections::HashMap;
+
+use crate::orchestrator::{Orchestrator, ORC};
+use crate::responses;
+use crate::utils::{unix_timestamp, FancyIVec};
+use crate::writs::Writ;
+
+// how many of a tag's newest writs get looked at to find its related tags
+const RELATED_TAG_SAMPLE: usize = 500;
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum TagMode {
+  And,
+  Or,
+}
+
+impl std::default::Default for TagMode {
+  fn default() -> Self {
+    TagMode::And
+  }
+}
+
+impl Orchestrator {
+  pub fn tag_count(&self, tag: &str) -> u64 {
+    match self.tag_counter.get(tag.as_bytes()) {
+      Ok(Some(raw)) => raw.to_u64(),
+      _ => 0,
+    }
+  }
+
+  // every writ id filed under a tag for a kind, and optionally a single author
+  pub fn tag_postings(&self, tag: &str, kind: &str, author_id: Option<u64>) -> impl DoubleEndedIterator<Item = IVec> {
+    self.tags_index
+      .scan_prefix(tag_scan_prefix(tag, kind, author_id))
+      .values()
+      .filter_map(|res| res.ok())
+  }
+
+  pub fn is_writ_tagged(&self, tag: &str, wid: &[u8]) -> bool {
+    self.tags_index.contains_key(tag_index_key(tag, wid)).unwrap_or(false)
+  }
+
+  // like tag_postings but newest first, and only the ones older than `before` when given
+  pub fn tag_postings_before(
+    &self,
+    tag: &str,
+    kind: &str,
+    author_id: Option<u64>,
+    before: Option<&[u8]>,
+  ) -> Box<dyn Iterator<Item = IVec>> {
+    let prefix = tag_scan_prefix(tag, kind, author_id);
+    match before {
+      Some(wid) => Box::new(
+        self.tags_index
+          .range(prefix.clone()..tag_index_key(tag, wid))
+          .rev()
+          .filter_map(|res| res.ok())
+          .take_while(move |(key, _)| key.starts_with(&prefix))
+          .map(|(_, wid)| wid)
+      ),
+      None => Box::new(self.tag_postings(tag, kind, author_id).rev()),
+    }
+  }
+
+  // resolves a set of tags to writ ids using nothing but tags_index, lazily and newest first,
+  // AND walks the rarest tag's postings and probes the others for each one,
+  // OR merges the already ordered postings of every tag, omitted tags are probed per id,
+  // so callers only pay for as many ids as they actually take
+  pub fn tagged_writ_ids<'a>(
+    &'a self,
+    tags: &[String],
+    omit_tags: &'a [String],
+    mode: TagMode,
+    kind: &str,
+    author_id: Option<u64>,
+    before: Option<&[u8]>,
+  ) -> Box<dyn Iterator<Item = IVec> + 'a> {
+    let tags: Vec<&String> = tags.iter().unique().collect();
+
+    let matched: Box<dyn Iterator<Item = IVec> + 'a> = match mode {
+      TagMode::And => {
+        let mut planned: Vec<(&String, u64)> = tags.into_iter()
+          .map(|tag| (tag, self.tag_count(tag)))
+          .collect();
+        planned.sort_by_key(|(_, count)| *count);
+
+        let rarest = match planned.first() {
+          Some((tag, count)) if *count > 0 => tag.to_string(),
+          _ => return Box::new(std::iter::empty()),
+        };
+        let rest: Vec<String> = planned[1..].iter().map(|(tag, _)| tag.to_string()).collect();
+
+        Box::new(
+          self.tag_postings_before(&rarest, kind, author_id, before)
+            .filter(move |wid| rest.iter().all(|tag| self.is_writ_tagged(tag, wid)))
+        )
+      },
+      TagMode::Or => Box::new(
+        tags.into_iter()
+          .map(|tag| self.tag_postings_before(tag, kind, author_id, before))
+          .kmerge_by(|a, b| a > b)
+          .dedup()
+      ),
+    };
+
+    Box::new(matched.filter(move |wid| {
+      !omit_tags.iter().any(|tag| self.is_writ_tagged(tag, wid))
+    }))
+  }
+
+  // keeps public_tags and public_tag_counter in step with a writ, whatever it was listed
+  // under before is dropped, and it's only listed again while it's public
+  pub fn sync_public_tags_in_transaction(
+    &self,
+    public_tags: &TransactionalTree,
+    public_tag_counter: &TransactionalTree,
+    wid: &[u8],
+    writ: Option<&Writ>,
+  ) -> ConflictableTransactionResult<(), ()> {
+    if let Some(raw) = public_tags.remove(wid)? {
+      let old_tags: Vec<String> = BorshDeserialize::try_from_slice(&raw).unwrap();
+      for tag in old_tags.iter() {
+        for key in public_tag_count_keys(tag, wid).iter() {
+          let count = public_tag_counter.get(key)?.map_or(0, |raw| raw.to_u64());
+          if count <= 1 {
+            public_tag_counter.remove(key.as_slice())?;
+          } else {
+            public_tag_counter.insert(key.as_slice(), IVec::from_u64(count - 1))?;
+          }
+        }
+      }
+    }
+
+    if let Some(writ) = writ.filter(|w| w.public) {
+      let tags: Vec<String> = writ.tags.iter().unique().cloned().collect();
+      for tag in tags.iter() {
+        for key in public_tag_count_keys(tag, wid).iter() {
+          let count = public_tag_counter.get(key)?.map_or(0, |raw| raw.to_u64());
+          public_tag_counter.insert(key.as_slice(), IVec::from_u64(count + 1))?;
+        }
+      }
+      public_tags.insert(wid, tags.try_to_vec().unwrap())?;
+    }
+    Ok(())
+  }
+
+  // how many public writs use a tag, either overall or just for one kind of writ
+  pub fn tag_count_for(&self, tag: &str, kind: Option<&str>) -> u64 {
+    let key = match kind {
+      Some(kind) => kind_tag_key(kind, tag),
+      None => tag.as_bytes().to_vec(),
+    };
+    match self.public_tag_counter.get(key) {
+      Ok(Some(raw)) => raw.to_u64(),
+      _ => 0,
+    }
+  }
+
+  pub fn tag_directory(&self, kind: Option<&str>, prefix: &str) -> Vec<TagCount> {
+    // tags can't hold a colon, so the overall counts are the keys without one
+    let (scan, skip) = match kind {
+      Some(kind) => (kind_tag_key(kind, prefix), kind.len() + 1),
+      None => (prefix.as_bytes().to_vec(), 0),
+    };
+    let mut tags: Vec<TagCount> = self.public_tag_counter
+      .scan_prefix(scan)
+      .filter_map(|res| res.ok())
+      .filter_map(|(key, raw_count)| {
+        let tag = String::from_utf8_lossy(&key[skip..]).to_string();
+        let count = raw_count.to_u64();
+        (!tag.contains(':') && count > 0).then(|| TagCount { tag, count })
+      })
+      .collect();
+
+    tags.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.tag.cmp(&b.tag)));
+    tags
+  }
+
+  // tags that turn up on the same public writs as the given one, most shared first
+  pub fn related_tags(&self, tag: &str, kind: &str) -> Vec<TagCount> {
+    let mut shared: HashMap<String, u64> = HashMap::new();
+
+    let sample = self.tag_postings(tag, kind, None)
+      .rev()
+      .filter_map(|wid| self.public_tags.get(&wid).ok().flatten())
+      .take(RELATED_TAG_SAMPLE);
+
+    for raw in sample {
+      let tags: Vec<String> = BorshDeserialize::try_from_slice(&raw).unwrap();
+      for other in tags.into_iter() {
+        if other != tag {
+          *shared.entry(other).or_insert(0) += 1;
+        }
+      }
+    }
+
+    let mut related: Vec<TagCount> = shared.into_iter()
+      .map(|(tag, count)| TagCount { tag, count })
+      .collect();
+    related.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.tag.cmp(&b.tag)));
+    related
+  }
+}
+
+const PUBLIC_TAGS_MIGRATION: &[u8] = b"migration:public_tags_index";
+
+// public_tags came after tags_index, so it's filled in once from the writs there already are
+pub fn index_public_tags(
+  db: &sled::Db,
+  writs: &sled::Tree,
+  public_tags: &sled::Tree,
+  public_tag_counter: &sled::Tree,
+) {
+  if db.contains_key(PUBLIC_TAGS_MIGRATION).unwrap_or(false) {
+    return;
+  }
+
+  public_tags.clear().unwrap();
+  public_tag_counter.clear().unwrap();
+
+  let mut counts: HashMap<Vec<u8>, u64> = HashMap::new();
+  for res in writs.iter() {
+    let (wid, raw) = res.expect("failed to read the writs tree while indexing public tags");
+    let writ = Writ::try_from_slice(&raw).unwrap();
+    if !writ.public {
+      continue;
+    }
+
+    let tags: Vec<String> = writ.tags.iter().unique().cloned().collect();
+    for tag in tags.iter() {
+      for key in public_tag_count_keys(tag, &wid).iter() {
+        *counts.entry(key.clone()).or_insert(0) += 1;
+      }
+    }
+    public_tags.insert(wid, tags.try_to_vec().unwrap()).unwrap();
+  }
+
+  for (key, count) in counts.iter() {
+    public_tag_counter.insert(key.as_slice(), IVec::from_u64(*count)).unwrap();
+  }
+
+  public_tags.flush().unwrap();
+  public_tag_counter.flush().unwrap();
+  db.insert(PUBLIC_TAGS_MIGRATION, &unix_timestamp().to_be_bytes()).unwrap();
+}
+
+// {kind}:{tag}, next to the plain {tag} keys holding the count over every kind
+#[inline]
+pub fn kind_tag_key(kind: &str, tag: &str) -> Vec<u8> {
+  format!("{}:{}", kind, tag).into_bytes()
+}
+
+// a writ's kind is the front of its id, so it's known even once the writ itself is gone
+fn public_tag_count_keys(tag: &str, wid: &[u8]) -> [Vec<u8>; 2] {
+  let kind = String::from_utf8_lossy(&wid[..4]);
+  [tag.as_bytes().to_vec(), kind_tag_key(&kind, tag)]
+}
+
+#[inline]
+pub fn tag_index_key(tag: &str, wid: &[u8]) -> Vec<u8> {
+  let mut key = Vec::with_capacity(tag.len() + 21);
+  key.extend_from_slice(tag.as_bytes());
+  key.extend_from_slice(b":");
+  key.extend_from_slice(wid);
+  key
+}
+
+pub fn tag_scan_prefix(tag: &str, kind: &str, author_id: Option<u64>) -> Vec<u8> {
+  let mut prefix = Vec::with_capacity(tag.len() + 13);
+  prefix.extend_from_slice(tag.as_bytes());
+  prefix.extend_from_slice(b":");
+  prefix.extend_from_slice(kind.as_bytes());
+  if let Some(author_id) = author_id {
+    prefix.extend_from_slice(&author_id.to_be_bytes());
+  }
+  prefix
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct TagCount {
+  pub tag: String,
+  pub count: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct TagDirectoryQuery {
+  pub kind: Option<String>,
+  pub prefix: Option<String>,
+  pub amount: Option<usize>,
+  pub page: Option<usize>,
+}
+
+#[get("/tags")]
+pub async fn tag_directory(query: web::Query<TagDirectoryQuery>) -> HttpResponse {
+  let amount = query.amount.unwrap_or(100);
+  if amount > 1000 {
+    return responses::BadRequest("you can't list more than 1000 tags at a time");
+  }
+
+  let tags = ORC.tag_directory(query.kind.as_deref(), query.prefix.as_deref().unwrap_or(""))
+    .into_iter()
+    .skip(query.page.unwrap_or(0) * amount)
+    .take(amount)
+    .collect::<Vec<TagCount>>();
+
+  if tags.len() == 0 {
+    return responses::NotFound("no tags matched");
+  }
+  responses::Ok(tags)
+}
+
+#[get("/tags/autocomplete/{prefix}")]
+pub async fn autocomplete_tags(
+  prefix: web::Path<String>,
+  query: web::Query<TagDirectoryQuery>,
+) -> HttpResponse {
+  let prefix = prefix.trim();
+  if prefix.is_empty() {
+    return responses::BadRequest("give at least one character to autocomplete");
+  }
+
+  let amount = query.amount.unwrap_or(10).min(50);
+  let tags = ORC.tag_directory(query.kind.as_deref(), prefix)
+    .into_iter()
+    .take(amount)
+    .collect::<Vec<TagCount>>();
+
+  responses::Ok(tags)
+}
+
+#[get("/tags/related/{tag}")]
+pub async fn related_tags(
+  tag: web::Path<String>,
+  query: web::Query<TagDirectoryQuery>,
+) -> HttpResponse {
+  let kind = query.kind.as_deref().unwrap_or("post");
+  let amount = query.amount.unwrap_or(20).min(100);
+
+  let related = ORC.related_tags(tag.as_str(), kind)
+    .into_iter()
+    .take(amount)
+    .collect::<Vec<TagCount>>();
+
+  if related.len() == 0 {
+    return responses::NotFound("that tag has no related tags");
+  }
+  responses::Ok(related)
+}
@@ -1,4 +1,8 @@
-use comrak::{markdown_to_html, ComrakOptions};
+use comrak::{
+  markdown_to_html_with_plugins,
+  plugins::syntect::SyntectAdapter,
+  ComrakOptions, ComrakPlugins,
+};
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use regex::Regex;
 use sled::IVec;
@@ -24,6 +28,11 @@ static COMRAK_OPTS: SyncLazy<ComrakOptions> = SyncLazy::new(|| {
   md_opts
 });
 
+// highlighted with inline styles, so posts look right without any client side highlighter
+static HIGHLIGHTER: SyncLazy<SyntectAdapter<'static>> = SyncLazy::new(|| {
+  SyntectAdapter::new("base16-ocean.dark")
+});
+
 static EMAIL_REGEX: SyncLazy<Regex> = SyncLazy::new(|| {
   Regex::new(
     r"^([a-z0-9_+]([a-z0-9_+.]*[a-z0-9_+])?)@([a-z0-9]+([\-\.]{1}[a-z0-9]+)*\.[a-z]{2,6})"
@@ -184,6 +193,20 @@ pub fn is_email_ok(email: &str) -> bool {
 pub fn i64_is_zero(i: &i64) -> bool {
   *i == 0
 }
+
+pub fn to_hex(bytes: &[u8]) -> String {
+  bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+  if hex.len() % 2 != 0 || !hex.is_ascii() {
+    return None;
+  }
+  (0..hex.len())
+    .step_by(2)
+    .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+    .collect()
+}
 /*
 pub fn read_be_u64(input: &mut &[u8]) -> u64 {
   let (int_bytes, rest) = input.split_at(std::mem::size_of::<u64>());
@@ -216,7 +239,9 @@ pub fn string_from_ivec(ivec: IVec) -> String {
 }
 */
 pub fn render_md(html: &str) -> String {
-  markdown_to_html(html, &COMRAK_OPTS)
+  let mut plugins = ComrakPlugins::default();
+  plugins.render.codefence_syntax_highlighter = Some(&*HIGHLIGHTER);
+  crate::images::add_media_srcsets(markdown_to_html_with_plugins(html, &COMRAK_OPTS, &plugins))
 }
 
 /*
@@ -1,4 +1,4 @@
-use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse};
+use actix_web::{delete, get, http::StatusCode, post, put, web, HttpRequest, HttpResponse};
 use borsh::{BorshDeserialize, BorshSerialize};
 use itertools::Itertools;
 use rayon::prelude::*;
@@ -11,13 +11,26 @@ use std::convert::TryInto;
 
 // use super::CONF;
 use crate::auth::User;
+use crate::caching::{add_validators, forget_render, is_fresh, PAGE_CACHE_CONTROL, PRIVATE_CACHE_CONTROL};
+use crate::sitemap::invalidate_sitemap;
+use crate::collaborators::CoAuthor;
 use crate::comments::Comment;
+use crate::media::media_references;
 use crate::orchestrator::{Orchestrator, ORC};
-use crate::utils::{datetime_from_unix_timestamp, render_md, unix_timestamp, FancyBool, FancyIVec};
+use crate::revisions::{revision_counter_key, revision_key, WritRevision};
+use crate::reading::{table_of_contents, ReadingStats, TocEntry};
+use crate::rankings::{rank_scan_prefix, TopWindow, WritRanks, WritSort};
+use crate::reactions::ReactionCount;
+use crate::responses::APIResponse;
+use crate::sanitizer::sanitize_html;
+use crate::share_links::ShareQuery;
+use crate::search::{search_query_terms, writ_search_terms};
+use crate::tags::TagMode;
+use crate::utils::{datetime_from_unix_timestamp, from_hex, render_md, to_hex, unix_timestamp, FancyBool, FancyIVec};
 
 impl Orchestrator {
   pub fn new_writ_id(&self, author_id: u64, kind: &[u8]) -> Option<WritID> {
-    if kind.len() == 4 {
+    if kind.len() == 4 && self.kinds.contains_key(kind).unwrap_or(false) {
       return WritID::new(kind, author_id);
     }
     None
@@ -103,7 +116,14 @@ impl Orchestrator {
       return false;
     }
 
-    let res: TransactionResult<(), ()> = (
+    let revision_keys = self.writ_revision_keys(writ_id);
+    let series_keys = self.writ_series_keys(&writ_id.to_bin());
+    let (view_keys, referrer_keys) = self.writ_stats_keys(&writ_id.to_bin());
+    let removed_tags = self.writ_by_id_bytes(&writ_id.to_bin()).map_or(vec![], |w| w.tags);
+    let share_keys = self.writ_share_keys(&writ_id.to_bin());
+
+    // more trees than sled's tuple transactions go up to, so they're handed over as a slice
+    let trees = [
       &self.content,
       &self.raw_content,
       &self.titles,
@@ -114,20 +134,73 @@ impl Orchestrator {
       &self.tags_index,
       &self.tag_counter,
       &self.comment_settings,
-    )
+      &self.writ_revisions,
+      &self.scheduled_writs,
+      &self.search_index,
+      &self.search_terms,
+      &self.writ_rankings,
+      &self.writ_ranks,
+      &self.retired_slugs,
+      &self.slug_history,
+      &self.series,
+      &self.writ_series,
+      &self.media,
+      &self.writ_media,
+      &self.writ_collaborators,
+      &self.user_collaborations,
+      &self.writ_views,
+      &self.writ_referrers,
+      &self.share_links,
+      &self.writ_share_links,
+      &self.public_tags,
+      &self.public_tag_counter,
+      &self.site_views,
+      &self.archive_months,
+    ];
+
+    let res: TransactionResult<Vec<String>, ()> = (&trees[..])
       .transaction(
-        |(
-          ctn,
-          raw_ctn,
-          titles,
-          slugs,
-          dates,
-          votes,
-          writs,
-          tags_index,
-          tag_counter,
-          comment_settings,
-        )| {
+        |trs| {
+          let (
+            ctn,
+            raw_ctn,
+            titles,
+            slugs,
+            dates,
+            votes,
+            writs,
+            tags_index,
+            tag_counter,
+            comment_settings,
+            revisions,
+            scheduled,
+            search_index,
+            search_terms,
+            rankings,
+            ranks,
+            retired_slugs,
+            slug_history,
+            series_tree,
+            writ_series,
+            media,
+            writ_media,
+            writ_collaborators,
+            user_collaborations,
+            writ_views,
+            writ_referrers,
+            share_links,
+            writ_share_links,
+            public_tags,
+            public_tag_counter,
+            site_views,
+            archive_months,
+          ) = (
+            &trs[0], &trs[1], &trs[2], &trs[3], &trs[4], &trs[5], &trs[6], &trs[7], &trs[8], &trs[9],
+            &trs[10], &trs[11], &trs[12], &trs[13], &trs[14], &trs[15], &trs[16], &trs[17], &trs[18], &trs[19],
+            &trs[20], &trs[21], &trs[22], &trs[23], &trs[24], &trs[25], &trs[26], &trs[27], &trs[28], &trs[29],
+            &trs[30], &trs[31],
+          );
+
           let wid_vec = writ_id.to_bin();
           let wid = wid_vec.as_slice();
 
@@ -150,16 +223,47 @@ impl Orchestrator {
             &writ_id,
             writ.tags.as_slice()
           )?;
+          self.sync_public_tags_in_transaction(public_tags, public_tag_counter, wid, None)?;
+          self.count_archive_month_in_transaction(archive_months, Some(&writ), None)?;
 
           titles.remove(writ.title_key().as_bytes())?;
           slugs.remove(writ.slug_key().as_bytes())?;
-          dates.remove(writ.date_key().as_bytes())?;
+          if let Some(publish_at) = writ.publish_at {
+            scheduled.remove(scheduled_key(publish_at, wid))?;
+          } else {
+            dates.remove(writ.date_key().as_bytes())?;
+          }
+
+          for key in revision_keys.iter() {
+            revisions.remove(key)?;
+          }
 
-          Ok(())
+          self.remove_writ_search_terms_in_transaction(search_index, search_terms, wid)?;
+          self.remove_writ_ranks_in_transaction(rankings, ranks, wid)?;
+          self.remove_slug_history_in_transaction(retired_slugs, slug_history, wid)?;
+          self.remove_writ_from_series_in_transaction(series_tree, writ_series, &writ, wid, &series_keys)?;
+          self.remove_writ_collaborators_in_transaction(writ_collaborators, user_collaborations, wid)?;
+          self.remove_writ_stats_in_transaction(writ_views, site_views, writ_referrers, &view_keys, &referrer_keys)?;
+          self.remove_writ_share_links_in_transaction(share_links, writ_share_links, wid, &share_keys)?;
+
+          self.relink_writ_media_in_transaction(media, writ_media, &writ_id.to_string(), wid, &Default::default())
         },
       );
 
-    if res.is_ok() {
+    if let Ok(orphaned_media) = res {
+      self.remove_media_files(&orphaned_media);
+      self.forget_related_writs(&writ_id.to_bin());
+      self.touch_related_tags(&removed_tags);
+      self.forget_reactions(&self.writ_reactions, &self.writ_reactors, &writ_id.to_string());
+      forget_render(&writ_id.to_string());
+      invalidate_sitemap();
+
+      if let Err(e) = self.id_counter.remove(revision_counter_key(writ_id).as_bytes()) {
+        if self.dev_mode {
+          println!("failed to remove a removed writ's revision counter: {}", e);
+        }
+      }
+
       let mut iter = self.comments.scan_prefix(writ_id.to_string());
       while let Some(Ok(res)) = iter.next() {
         let comment = Comment::try_from_slice(&res.1).unwrap();
@@ -172,7 +276,12 @@ impl Orchestrator {
     false
   }
 
-  pub fn writ_query(&self, mut query: WritQuery, o_usr: Option<&User>) -> Option<Vec<Writ>> {
+  pub fn writ_query(&self, query: WritQuery, o_usr: Option<&User>) -> Option<Vec<Writ>> {
+    self.writ_query_page(query, o_usr).map(|page| page.writs)
+  }
+
+  // like writ_query but also hands back a cursor to continue from when there might be more
+  pub fn writ_query_page(&self, mut query: WritQuery, o_usr: Option<&User>) -> Option<WritPage<Writ>> {
     let is_admin = o_usr.as_ref().map_or(false, |usr| self.is_admin(usr.id.clone()));
 
     let amount = *query.amount.as_ref().unwrap_or(&20);
@@ -181,6 +290,25 @@ impl Orchestrator {
       return None;
     }
 
+    let cursor = match query.cursor.as_deref() {
+      None | Some("") => None,
+      Some(raw) => match WritCursor::decode(raw) {
+        Some(cursor) => Some(cursor),
+        None => return None,
+      },
+    };
+    let mut next_cursor: Option<WritCursor> = None;
+    let mut truncated = false;
+
+    // a series is just an ordered list of ids, narrowed down by any ids asked for
+    if let Some(series_id) = &query.series {
+      let series = self.series_by_id(series_id)?;
+      query.ids = Some(match &query.ids {
+        Some(ids) => series.writs.into_iter().filter(|id| ids.contains(id)).collect(),
+        None => series.writs,
+      });
+    }
+
     let mut writs: Vec<Writ> = vec![];
     let mut count: u64 = 0;
 
@@ -227,9 +355,32 @@ impl Orchestrator {
       }
     }
 
+    let sort = query.sort.unwrap_or_default();
+    if sort == WritSort::Top {
+      if let Some(window) = query.top_window.and_then(|w| w.seconds()) {
+        let since = unix_timestamp() - window;
+        query.posted_after = Some(query.posted_after.map_or(since, |after| after.max(since)));
+      }
+    }
+
     let user_attributes = o_usr.as_ref().map(|usr| self.user_attributes(usr.id));
+    // the ids were already narrowed down to writs the user collaborates on
+    let collaborating = query.collaborating.unwrap_or(false);
+    let tag_mode = query.tag_mode.unwrap_or_default();
 
     let check_writ_against_query = |writ: &Writ, date_scan: bool| {
+      if let Some(scheduled) = &query.scheduled {
+        if writ.publish_at.is_some() != *scheduled {
+          return false;
+        }
+      }
+
+      if let Some(drafts) = &query.drafts {
+        if writ.is_draft() != *drafts {
+          return false;
+        }
+      }
+
       if let Some(posted_before) = &query.posted_before {
         if writ.posted > *posted_before {
           return false;
@@ -284,7 +435,7 @@ impl Orchestrator {
       };
 
       if let Some(usr) = &o_usr {
-        if author_id == usr.id || is_admin {
+        if author_id == usr.id || is_admin || collaborating {
           if let Some(public) = &query.public {
             if writ.public != *public {
               return false;
@@ -317,10 +468,13 @@ impl Orchestrator {
       }
 
       if let Some(tags) = &query.tags {
-        for tag in tags {
-          if !writ.tags.contains(tag) {
-            return false;
-          }
+        let tagged = |tag: &String| writ.tags.contains(tag);
+        let matches = match tag_mode {
+          TagMode::And => tags.iter().all(tagged),
+          TagMode::Or => tags.iter().any(tagged),
+        };
+        if !matches {
+          return false;
         }
       }
 
@@ -348,21 +502,22 @@ impl Orchestrator {
     };
 
     if let Some(ids) = &query.ids {
-      let id_iter = {
-        let mut iter = ids.iter();
-        if query.page > 0 {
-          let skip_n = (query.page * amount) as usize;
-          if ids.len() < skip_n || iter.advance_by(skip_n).is_err() {
-            return None;
-          }
-        }
-        iter
+      let start = match &cursor {
+        Some(WritCursor::Offset(offset)) => *offset as usize,
+        Some(_) => return None,
+        None => (query.page * amount) as usize,
       };
+      if ids.len() < start {
+        return None;
+      }
 
-      for id in id_iter {
+      let mut consumed = start;
+      for id in ids.iter().skip(start) {
         if count == amount {
+          next_cursor = Some(WritCursor::Offset(consumed as u64));
           break;
         }
+        consumed += 1;
 
         if let Some(skip_ids) = &query.skip_ids {
           if skip_ids.contains(id) {
@@ -393,116 +548,231 @@ impl Orchestrator {
           writs.push(writ);
         }
       }
+    } else if let Some(search) = &query.search {
+      let terms = search_query_terms(search);
+      if terms.len() == 0 {
+        return None;
+      }
+
+      let (start, skip_n) = match &cursor {
+        Some(WritCursor::Offset(offset)) => (*offset as usize, 0),
+        Some(_) => return None,
+        None => (0, query.page * amount),
+      };
+
+      let (ranked, cut_short) = self.search_writ_ids(&terms, &query.kind, query.author_id);
+      truncated = cut_short;
+
+      let mut consumed = start;
+      for wid in ranked.into_iter().skip(start) {
+        if writs.len() as u64 == amount {
+          next_cursor = Some(WritCursor::Offset(consumed as u64));
+          break;
+        }
+        consumed += 1;
+
+        if let Some(skip_ids) = &query.skip_ids {
+          if skip_ids.contains(&WritID::from_bin(&wid).to_string()) {
+            continue;
+          }
+        }
+
+        if let Ok(Some(raw)) = self.writs.get(wid) {
+          let writ = Writ::try_from_slice(&raw).unwrap();
+          if check_writ_against_query(&writ, false) {
+            count += 1;
+            if count > skip_n {
+              writs.push(writ);
+            }
+          }
+        }
+      }
     } else if let Some(tags) = &query.tags {
-      if let Some(first) = tags.first() {
-        let mut partial_id = first.as_bytes().to_vec();
-        partial_id.extend_from_slice(b":");
-        partial_id.extend_from_slice(query.kind.as_bytes());
-        if let Some(author_id) = &query.author_id {
-          partial_id.extend_from_slice(&author_id.to_be_bytes());
+      let before = match &cursor {
+        Some(WritCursor::Key(last)) if !sort.is_ranked() => Some(last.as_slice()),
+        Some(WritCursor::Key(_)) => None,
+        Some(_) => return None,
+        None => None,
+      };
+
+      let tagged = self.tagged_writ_ids(
+        tags,
+        query.omit_tags.as_deref().unwrap_or(&[]),
+        tag_mode,
+        &query.kind,
+        query.author_id,
+        before,
+      );
+
+      let (tagged, skip_n): (Box<dyn Iterator<Item = IVec> + '_>, u64) = if sort.is_ranked() {
+        // ranking needs every match up front, highest first
+        let mut ranked: Vec<IVec> = tagged.collect();
+        ranked.sort_by_cached_key(|wid| {
+          std::cmp::Reverse(self.writ_ranks(wid).map_or(0, |r| r.score(sort)))
+        });
+
+        match &cursor {
+          Some(WritCursor::Key(last)) => {
+            let start = ranked.iter().position(|wid| wid.as_ref() == last.as_slice()).map_or(ranked.len(), |i| i + 1);
+            (Box::new(ranked.into_iter().skip(start)), 0)
+          },
+          _ => (Box::new(ranked.into_iter()), query.page * amount),
         }
+      } else if cursor.is_some() {
+        (tagged, 0)
+      } else {
+        (tagged, query.page * amount)
+      };
 
-        let mut ti_iter = self.tags_index.scan_prefix(partial_id);
+      let mut last_wid: Option<IVec> = None;
+      for wid in tagged {
+        if writs.len() as u64 == amount {
+          next_cursor = last_wid.map(|wid| WritCursor::Key(wid.to_vec()));
+          break;
+        }
+        last_wid = Some(wid.clone());
 
-        let skip_n = query.page * amount;
-        while let Some(Ok((_, wid))) = ti_iter.next_back() {
-          if (query.page == 0 && count == amount) || 
-             (count > skip_n && count - skip_n == amount)
-          { 
-              break;
+        if let Some(skip_ids) = &query.skip_ids {
+          if skip_ids.contains(&WritID::from_bin(&wid).to_string()) {
+            continue;
           }
+        }
 
-          if let Some(skip_ids) = &query.skip_ids {
-            let writ_id = WritID::from_bin(&wid);
-            if skip_ids.contains(&writ_id.to_string()) {
-              continue;
+        if let Ok(Some(raw)) = self.writs.get(&wid) {
+          let writ = Writ::try_from_slice(&raw).unwrap();
+          if check_writ_against_query(&writ, false) {
+            count += 1;
+            if count > skip_n {
+              writs.push(writ);
             }
           }
+        }
+      }
+    } else if sort.is_ranked() {
+      let partial_id = rank_scan_prefix(sort, &query.kind);
 
-          if let Ok(Some(raw)) = self.writs.get(wid) {
-            let writ = Writ::try_from_slice(&raw).unwrap();
-            if check_writ_against_query(&writ, false) {
-              count += 1;
-              if query.page == 0 || (query.page > 0 && count > skip_n) {
-                writs.push(writ);
-              }
-            }
+      let (mut rank_iter, skip_n) = match &cursor {
+        Some(WritCursor::Key(last)) if last.starts_with(&partial_id) => {
+          (self.writ_rankings.range(partial_id.clone()..last.clone()), 0)
+        },
+        Some(_) => return None,
+        None => (self.writ_rankings.scan_prefix(&partial_id), query.page * amount),
+      };
+
+      let mut last_key: Option<IVec> = None;
+      while let Some(Ok((key, wid))) = rank_iter.next_back() {
+        if writs.len() as u64 == amount {
+          next_cursor = last_key.map(|key| WritCursor::Key(key.to_vec()));
+          break;
+        }
+        last_key = Some(key);
+
+        if let Some(author_id) = &query.author_id {
+          if wid[4..12] != author_id.to_be_bytes() {
+            continue;
+          }
+        }
+
+        if let Some(skip_ids) = &query.skip_ids {
+          if skip_ids.contains(&WritID::from_bin(&wid).to_string()) {
+            continue;
           }
         }
 
-        if writs.len() > 0 {
-          return Some(writs);
+        if let Ok(Some(raw)) = self.writs.get(&wid) {
+          let writ = Writ::try_from_slice(&raw).unwrap();
+          if check_writ_against_query(&writ, false) {
+            count += 1;
+            if count > skip_n {
+              writs.push(writ);
+            }
+          }
         }
       }
     } else {
       let mut date = String::new();
       let now = OffsetDateTime::now_utc();
       if let Some(y) = &query.year {
-        date.push_str(&format!("{}", y));
+        if *y < 0 || *y > 9999 {
+          return None;
+        }
+        date.push_str(&format!("{:04}", y));
       }
       if let Some(m) = &query.month {
         if date.is_empty() {
-          date.push_str(&format!("{}", now.year()));
+          date.push_str(&format!("{:04}", now.year()));
         }
         if *m > 12 || *m == 0 {
           return None;
         }
-        date.push_str(&format!("{}", m));
+        date.push_str(&format!("{:02}", m));
       }
       if let Some(d) = &query.day {
         if date.is_empty() {
-          date.push_str(&format!("{}", now.year()));
+          date.push_str(&format!("{:04}", now.year()));
         }
         if query.month.is_none() {
-          date.push_str(&format!("{}", now.month()));
+          date.push_str(&format!("{:02}", now.month()));
         }
         if *d > 31 || *d == 0 {
           return None;
         }
-        date.push_str(&format!("{}", d));
+        date.push_str(&format!("{:02}", d));
       }
       if let Some(h) = &query.hour {
         if date.is_empty() {
-          date.push_str(&format!("{}", now.year()));
+          date.push_str(&format!("{:04}", now.year()));
         }
         if query.month.is_none() {
-          date.push_str(&format!("{}", now.month()));
+          date.push_str(&format!("{:02}", now.month()));
         }
         if query.day.is_none() {
-          date.push_str(&format!("{}", now.day()));
+          date.push_str(&format!("{:02}", now.day()));
         }
-        if *h > 24 {
+        if *h > 23 {
           return None;
         }
-        date.push_str(&format!("{}", h));
+        date.push_str(&format!("{:02}", h));
       }
 
       let date_scan = !date.is_empty();
 
-      let mut writ_iter = if date_scan {
-        let partial_date_id = format!("{}:{}", query.kind, date);
-        self.dates.scan_prefix(partial_date_id.as_bytes())
+      let (tree, partial_id) = if date_scan {
+        (&self.dates, format!("{}:{}", query.kind, date).into_bytes())
       } else {
         let mut partial_id = vec![];
         partial_id.extend_from_slice(query.kind.as_bytes());
         if let Some(author_id) = &query.author_id {
           partial_id.extend_from_slice(&author_id.to_be_bytes());
         }
-        self.writs.scan_prefix(&partial_id)
+        (&self.writs, partial_id)
+      };
+
+      let mut writ_iter = match &cursor {
+        Some(WritCursor::Key(last)) if last.starts_with(&partial_id) => {
+          tree.range(partial_id.clone()..last.clone())
+        },
+        Some(_) => return None,
+        None => tree.scan_prefix(&partial_id),
       };
 
-      if query.page > 0 {
+      if cursor.is_none() && query.page > 0 {
         let skip_n = (query.page * amount) as usize;
         if writ_iter.advance_back_by(skip_n).is_err() {
           return None;
         }
       }
 
+      let mut last_key: Option<IVec> = None;
       while let Some(Ok(res)) = writ_iter.next_back() {
-        if count == amount { break; }
+        if count == amount {
+          next_cursor = last_key.map(|key| WritCursor::Key(key.to_vec()));
+          break;
+        }
+        last_key = Some(res.0.clone());
 
         let writ: Writ = if date_scan {
-          let id = res.1.to_string();
+          let id = WritID::from_bin(&res.1).to_string();
           if let Some(skip_ids) = &query.skip_ids {
             if skip_ids.contains(&id) {
               continue;
@@ -538,7 +808,11 @@ impl Orchestrator {
     if writs.len() == 0 {
       return None;
     }
-    Some(writs)
+    Some(WritPage {
+      writs,
+      next_cursor: next_cursor.map(|cursor| cursor.encode()),
+      truncated,
+    })
   }
 
   pub fn public_writ_query(
@@ -546,37 +820,143 @@ impl Orchestrator {
     query: WritQuery,
     o_usr: Option<&User>,
   ) -> Option<Vec<PublicWrit>> {
+    self.public_writ_query_page(query, o_usr).map(|page| page.writs)
+  }
+
+  pub fn public_writ_query_page(
+    &self,
+    mut query: WritQuery,
+    o_usr: Option<&User>,
+  ) -> Option<WritPage<PublicWrit>> {
+    // collaborating loosens the visibility checks, so it's never taken from the client here
+    query.collaborating = None;
     let usr_id = o_usr.as_ref().map(|usr| usr.id);
     let with_content = query.with_content.unwrap_or(true);
-    if let Some(writs) = self.writ_query(query, o_usr) {
-      let public_writs = writs
+    let search_terms = query.search.as_ref().map(|search| search_query_terms(search));
+    if let Some(page) = self.writ_query_page(query, o_usr) {
+      let public_writs = page.writs
         .into_par_iter()
-        .filter_map(|w| w.public(&usr_id, with_content))
+        .filter_map(|w| {
+          let mut pw = w.public(&usr_id, with_content)?;
+          if let Some(terms) = &search_terms {
+            pw.snippet = self.writ_search_snippet(&w, terms);
+          }
+          Some(pw)
+        })
         .collect::<Vec<PublicWrit>>();
 
       if public_writs.len() > 0 {
-        return Some(public_writs);
+        return Some(WritPage {
+          writs: public_writs,
+          next_cursor: page.next_cursor,
+          truncated: page.truncated,
+        });
       }
     }
     None
   }
 
-  pub fn editable_writ_query(&self, mut query: WritQuery, usr: &User) -> Option<Vec<EditableWrit>> {
-    query.author_id = Some(usr.id.clone());
+  pub fn editable_writ_query(&self, query: WritQuery, usr: &User) -> Option<Vec<EditableWrit>> {
+    self.editable_writ_query_page(query, usr).map(|page| page.writs)
+  }
+
+  pub fn editable_writ_query_page(&self, mut query: WritQuery, usr: &User) -> Option<WritPage<EditableWrit>> {
+    if query.collaborating.take().unwrap_or(false) {
+      let shared = self.collaborating_writ_ids(usr.id);
+      query.ids = Some(match &query.ids {
+        Some(ids) => shared.into_iter().filter(|id| ids.contains(id)).collect(),
+        None => shared,
+      });
+      query.author_id = None;
+      query.authors = None;
+      // only set once the ids are down to writs the user actually collaborates on
+      query.collaborating = Some(true);
+    } else {
+      query.author_id = Some(usr.id.clone());
+    }
 
     let with_content = query.with_content.unwrap_or(false);
     let with_raw_content = query.with_raw_content.unwrap_or(true);
 
-    self.writ_query(query, Some(&usr)).and_then(|writs| {
-      let editable_writs = writs
+    self.writ_query_page(query, Some(&usr)).and_then(|page| {
+      let editable_writs = page.writs
         .into_par_iter()
         .filter_map(|w| w.editable(&usr, with_content, with_raw_content))
         .collect::<Vec<EditableWrit>>();
 
-      (editable_writs.len() > 0).qualify(editable_writs)
+      (editable_writs.len() > 0).qualify(WritPage {
+        writs: editable_writs,
+        next_cursor: page.next_cursor,
+        truncated: page.truncated,
+      })
     })
   }
 
+  pub fn publish_due_writs(&self) {
+    let now = unix_timestamp();
+    let due: &[u8] = &(now + 1).to_be_bytes();
+
+    let mut iter = self.scheduled_writs.range(..due);
+    while let Some(Ok((key, wid))) = iter.next() {
+      let publish_at = i64::from_be_bytes((&key[0..8]).try_into().unwrap());
+
+      let res: TransactionResult<(), ()> = (
+        &self.scheduled_writs,
+        &self.writs,
+        &self.dates,
+        &self.comment_settings,
+        &self.writ_rankings,
+        &self.writ_ranks,
+        &self.public_tags,
+        &self.public_tag_counter,
+        &self.archive_months,
+      ).transaction(|(scheduled, writs, dates, comment_settings, rankings, ranks, public_tags, public_tag_counter, archive_months)| {
+        scheduled.remove(key.clone())?;
+
+        let mut writ = match writs.get(&wid)? {
+          Some(raw) => Writ::try_from_slice(&raw).unwrap(),
+          None => return Ok(()),
+        };
+
+        // the schedule was changed or cleared since this entry was made
+        if writ.publish_at != Some(publish_at) {
+          return Ok(());
+        }
+
+        writ.public = true;
+        writ.publish_at = None;
+        writ.posted = now;
+
+        dates.insert(writ.date_key().as_bytes(), wid.clone())?;
+        self.update_writ_ranks_in_transaction(rankings, ranks, &wid, |r| r.posted = now)?;
+
+        if let Some(raw) = comment_settings.get(&wid)? {
+          let mut settings = CommentSettings::try_from_slice(&raw).unwrap();
+          settings.public = true;
+          comment_settings.insert(wid.clone(), settings.try_to_vec().unwrap())?;
+        }
+
+        writs.insert(wid.clone(), writ.try_to_vec().unwrap())?;
+        self.count_archive_month_in_transaction(archive_months, None, Some(&writ))?;
+        self.sync_public_tags_in_transaction(public_tags, public_tag_counter, &wid, Some(&writ))
+      });
+
+      if res.is_ok() {
+        invalidate_sitemap();
+        if let Some(writ) = self.writ_by_id_bytes(&wid) {
+          self.touch_related_tags(&writ.tags);
+        }
+      }
+
+      if self.dev_mode {
+        match res {
+          Ok(_) => println!("published scheduled writ {}", WritID::from_bin(&wid).to_string()),
+          Err(e) => println!("publishing a scheduled writ went wrong: {:?}", e),
+        }
+      }
+    }
+  }
+
   pub fn writ_by_id(&self, id: &str) -> Option<Writ> {
     if let Some(wid) = WritID::from_str(id) {
       return match self.writs.get(&wid.to_bin()) {
@@ -596,14 +976,14 @@ impl Orchestrator {
     }
     None
   }
-/*
+
   pub fn writ_by_id_bytes(&self, id: &[u8]) -> Option<Writ> {
     match self.writs.get(id) {
       Ok(w) => w.map(|raw| Writ::try_from_slice(&raw).unwrap()),
       Err(_) => None,
     }
   }
-
+/*
   pub fn writ_by_title(&self, kind: &str, title: &str) -> Option<Writ> {
     let key = format!("{}:{}", kind, title);
     if let Ok(Some(wid)) = self.titles.get(key.as_bytes()) {
@@ -628,25 +1008,166 @@ impl Orchestrator {
 */
 }
 
+#[inline]
+pub fn scheduled_key(publish_at: i64, wid: &[u8]) -> Vec<u8> {
+  let mut key = Vec::with_capacity(28);
+  key.extend_from_slice(&publish_at.to_be_bytes());
+  key.extend_from_slice(wid);
+  key
+}
+
+const WRIT_LAYOUT_VERSION: u64 = 1;
+const WRIT_LAYOUT_MIGRATION: &[u8] = b"migration:writ_layout_version";
+
+// how writs were stored before scheduling, the toc and reading stats, the unsanitized flag,
+// content hashes and modified times were added to them
+#[derive(BorshDeserialize)]
+struct WritLayoutV0 {
+  id: String,
+  title: String,
+  slug: String,
+  kind: String,
+  tags: Vec<String>,
+  posted: i64,
+  public: bool,
+  viewable_by: Vec<String>,
+  commentable: bool,
+  is_md: bool,
+}
+
+impl From<WritLayoutV0> for Writ {
+  fn from(old: WritLayoutV0) -> Self {
+    Writ {
+      id: old.id,
+      title: old.title,
+      slug: old.slug,
+      kind: old.kind,
+      tags: old.tags,
+      posted: old.posted,
+      public: old.public,
+      viewable_by: old.viewable_by,
+      commentable: old.commentable,
+      is_md: old.is_md,
+      publish_at: None,
+      toc: vec![],
+      word_count: 0,
+      reading_time: 0,
+      unsanitized: false,
+      content_hash: String::new(),
+      modified: old.posted,
+    }
+  }
+}
+
+// rewrites writs stored by older versions into the current layout, a row nothing can read stops
+// startup rather than having every later read of it panic
+pub fn migrate_writ_layouts(
+  db: &sled::Db,
+  writs: &sled::Tree,
+  raw_content: &sled::Tree,
+  content: &sled::Tree,
+  hasher: &sthash::Hasher,
+) {
+  let done = db.get(WRIT_LAYOUT_MIGRATION).unwrap().map_or(0, |raw| raw.to_u64());
+  if done >= WRIT_LAYOUT_VERSION {
+    return;
+  }
+
+  let mut upgraded = 0;
+  for res in writs.iter() {
+    let (wid, raw) = res.expect("failed to read the writs tree while migrating it");
+    if Writ::try_from_slice(&raw).is_ok() {
+      continue;
+    }
+
+    let mut writ: Writ = match WritLayoutV0::try_from_slice(&raw) {
+      Ok(old) => old.into(),
+      Err(_) => panic!(
+        "writ {} is stored in a layout none of the migrations know, refusing to start",
+        WritID::from_bin(&wid).to_string()
+      ),
+    };
+
+    let raw_md = raw_content.get(&wid).unwrap().map_or(String::new(), |raw| raw.to_string());
+    let html = content.get(&wid).unwrap().map_or(String::new(), |raw| raw.to_string());
+
+    let stats = ReadingStats::of(&html);
+    writ.word_count = stats.word_count;
+    writ.reading_time = stats.reading_time;
+    writ.toc = table_of_contents(&html);
+
+    let mut hashed = raw_md.into_bytes();
+    hashed.extend_from_slice(html.as_bytes());
+    writ.content_hash = to_hex(&hasher.hash(&hashed)[..16]);
+
+    writs.insert(wid, writ.try_to_vec().unwrap()).unwrap();
+    upgraded += 1;
+  }
+
+  writs.flush().unwrap();
+  db.insert(WRIT_LAYOUT_MIGRATION, IVec::from_u64(WRIT_LAYOUT_VERSION)).unwrap();
+  if upgraded > 0 {
+    println!("moved {} writs over to storage layout v{}", upgraded, WRIT_LAYOUT_VERSION);
+  }
+}
+
+const ZERO_PADDED_DATES_MIGRATION: &[u8] = b"migration:zero_padded_date_index";
+
+// date keys used to be unpadded, so 2021-1-12 and 2021-11-2 both came out as 2021112
+pub fn migrate_date_index(db: &sled::Db, dates: &sled::Tree, writs: &sled::Tree) {
+  if db.contains_key(ZERO_PADDED_DATES_MIGRATION).unwrap_or(false) {
+    return;
+  }
+
+  // everything's read before the old index goes, a writ that can't be read stops the
+  // migration right there rather than quietly dropping out of the index for good
+  let mut entries = vec![];
+  for res in writs.iter() {
+    let (wid, raw) = res.expect("failed to read the writs tree while rebuilding the date index");
+    let writ = Writ::try_from_slice(&raw)
+      .unwrap_or_else(|e| panic!("writ {} can't be read to rebuild the date index: {}", to_hex(&wid), e));
+    // scheduled writs only get a date once they go out
+    if writ.publish_at.is_none() {
+      entries.push((writ.date_key(), wid));
+    }
+  }
+
+  dates.clear().unwrap();
+  for (key, wid) in entries.iter() {
+    dates.insert(key.as_bytes(), wid).unwrap();
+  }
+  let reindexed = entries.len();
+
+  dates.flush().unwrap();
+  db.insert(ZERO_PADDED_DATES_MIGRATION, &unix_timestamp().to_be_bytes()).unwrap();
+  println!("rebuilt the date index with zero-padded keys for {} writs", reindexed);
+}
+
 #[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct WritQuery {
   pub title: Option<String>,
   pub slug: Option<String>,
+  pub search: Option<String>,
 
   pub tags: Option<Vec<String>>,
   pub omit_tags: Option<Vec<String>>,
+  pub tag_mode: Option<TagMode>,
   pub viewable_by: Option<Vec<String>>,
 
   pub ids: Option<Vec<String>>,
   pub skip_ids: Option<Vec<String>>,
+  pub series: Option<String>,
 
   pub authors: Option<Vec<String>>,
 
   pub public: Option<bool>,
+  pub drafts: Option<bool>,
+  pub scheduled: Option<bool>,
   pub author_name: Option<String>,
   pub author_handle: Option<String>,
   pub author_id: Option<u64>,
+  pub collaborating: Option<bool>,
 
   pub posted_before: Option<i64>,
   pub posted_after: Option<i64>,
@@ -658,6 +1179,10 @@ pub struct WritQuery {
 
   pub amount: Option<u64>,
   pub page: u64,
+  pub cursor: Option<String>,
+
+  pub sort: Option<WritSort>,
+  pub top_window: Option<TopWindow>,
 
   pub with_content: Option<bool>,
   pub with_raw_content: Option<bool>,
@@ -670,16 +1195,22 @@ impl std::default::Default for WritQuery {
     WritQuery {
       title: None,
       slug: None,
+      search: None,
       tags: None,
       omit_tags: None,
+      tag_mode: None,
       viewable_by: None,
       ids: None,
       skip_ids: None,
+      series: None,
       authors: None,
       public: None,
+      drafts: None,
+      scheduled: None,
       author_name: None,
       author_handle: None,
       author_id: None,
+      collaborating: None,
       posted_before: None,
       posted_after: None,
       year: None,
@@ -690,15 +1221,63 @@ impl std::default::Default for WritQuery {
       with_raw_content: None,
       amount: None,
       page: 0,
+      cursor: None,
+      sort: None,
+      top_window: None,
       kind: "post".to_string(),
     }
   }
 }
 
+// opaque position a writ query can pick up from again,
+// either an index into a list of ids or the last key a tree scan got to
+#[derive(BorshSerialize, BorshDeserialize, Clone, PartialEq, Debug)]
+pub enum WritCursor {
+  Offset(u64),
+  Key(Vec<u8>),
+}
+
+impl WritCursor {
+  pub fn encode(&self) -> String {
+    to_hex(&self.try_to_vec().unwrap())
+  }
+
+  pub fn decode(raw: &str) -> Option<Self> {
+    Self::try_from_slice(&from_hex(raw)?).ok()
+  }
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct WritPage<T> {
+  pub writs: Vec<T>,
+  pub next_cursor: Option<String>,
+  // a search term matched more writs than get looked at, so some may be missing
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub truncated: bool,
+}
+
+impl<T: Serialize> WritPage<T> {
+  // plain page numbered queries keep getting a bare list back,
+  // with anything else about the page left to the headers
+  pub fn respond(self, paged: bool) -> HttpResponse {
+    if paged {
+      return HttpResponse::Ok().json(self);
+    }
+    let mut res = HttpResponse::Ok();
+    if let Some(cursor) = &self.next_cursor {
+      res.append_header(("X-Next-Cursor", cursor.as_str()));
+    }
+    if self.truncated {
+      res.append_header(("X-Search-Truncated", "true"));
+    }
+    res.json(self.writs)
+  }
+}
+
 pub struct WritID {
-  kind: [u8; 4],
-  author: u64,
-  id: u64,
+  pub kind: [u8; 4],
+  pub author: u64,
+  pub id: u64,
 }
 
 impl WritID {
@@ -771,17 +1350,29 @@ impl WritID {
 
 #[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
 pub struct PublicWrit {
-  id: String, // {author_id}:{writ_id}
-  author_name: String,
-  author_handle: String,
-  title: String,
-  kind: String,
-  content: Option<String>,
-  tags: Vec<String>,
-  posted: i64,
-  commentable: bool,
-  you_voted: Option<bool>,
-  vote: i64,
+  pub id: String, // {author_id}:{writ_id}
+  pub author_name: String,
+  pub author_handle: String,
+  pub title: String,
+  pub kind: String,
+  pub content: Option<String>,
+  pub tags: Vec<String>,
+  pub posted: i64,
+  pub commentable: bool,
+  pub you_voted: Option<bool>,
+  pub vote: i64,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub reactions: Vec<ReactionCount>,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub you_reacted: Vec<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub snippet: Option<String>,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub co_authors: Vec<CoAuthor>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub toc: Option<Vec<TocEntry>>,
+  pub word_count: u64,
+  pub reading_time: u64,
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
@@ -796,6 +1387,13 @@ pub struct Writ {
   pub viewable_by: Vec<String>,
   pub commentable: bool,
   pub is_md: bool,
+  pub publish_at: Option<i64>,
+  pub toc: Vec<TocEntry>,
+  pub word_count: u64,
+  pub reading_time: u64,
+  pub unsanitized: bool, // an admin let its raw html through as is
+  pub content_hash: String, // hex, over the source and what it rendered to
+  pub modified: i64, // last commit
 }
 
 impl Writ {
@@ -822,6 +1420,11 @@ impl Writ {
   pub fn writ_id(&self) -> Option<WritID> {
     WritID::from_str(&self.id)
   }
+
+  #[inline]
+  pub fn is_draft(&self) -> bool {
+    !self.public && self.publish_at.is_none()
+  }
 /*
   pub fn content(&self) -> Option<String> {
     if let Some(wid) = self.writ_id() {
@@ -852,20 +1455,30 @@ impl Writ {
     None
   }
 
+  // public writs are there for anyone, the rest only for those who can edit them
+  pub fn is_visible_to(&self, requestor_id: &Option<u64>) -> bool {
+    if self.public {
+      return true;
+    }
+    match (requestor_id, WritID::from_str(&self.id)) {
+      (Some(req_id), Some(writ_id)) => ORC.can_edit_writ(*req_id, &writ_id),
+      _ => false,
+    }
+  }
+
   pub fn public(&self, requestor_id: &Option<u64>, with_content: bool) -> Option<PublicWrit> {
     let author_id = match self.author_id() {
       Some(au_id) => au_id,
       None => return None,
     };
 
-    if !self.public {
-      if let Some(req_id) = requestor_id {
-        if author_id != *req_id {
-          return None;
-        }
-      } else {
-        return None;
-      }
+    let writ_id = match WritID::from_str(&self.id) {
+      Some(wid) => wid,
+      None => return None,
+    };
+
+    if !self.is_visible_to(requestor_id) {
+      return None;
     }
 
     let (author_name, author_handle) = if let Some(author) = ORC.user_by_id(author_id) {
@@ -874,10 +1487,6 @@ impl Writ {
       ("Unknown".to_string(), "Unknown".to_string())
     };
 
-    let writ_id = match WritID::from_str(&self.id) {
-      Some(wid) => wid,
-      None => return None,
-    };
     let wid = writ_id.to_bin();
 
     let vote: i64 = if let Ok(Some(res)) = ORC.votes.get(&wid) {
@@ -922,6 +1531,13 @@ impl Writ {
       commentable: self.commentable,
       vote,
       you_voted,
+      reactions: ORC.reaction_counts(&ORC.writ_reactions, &self.id),
+      you_reacted: ORC.you_reacted(&ORC.writ_reactors, &self.id, requestor_id),
+      snippet: None,
+      co_authors: ORC.co_authors(&wid),
+      toc: with_content.qualify(self.toc.clone()),
+      word_count: self.word_count,
+      reading_time: self.reading_time,
     })
   }
 
@@ -936,10 +1552,6 @@ impl Writ {
       None => return None,
     };
 
-    if author_id != author.id {
-      return None;
-    }
-
     let writ_id = match self.writ_id() {
       Some(wid) => wid,
       None => return None,
@@ -947,6 +1559,10 @@ impl Writ {
     let wid_vec = writ_id.to_bin();
     let wid = wid_vec.as_slice();
 
+    if author_id != author.id && ORC.collaborator_role(author.id, wid).is_none() {
+      return None;
+    }
+
     let content = if with_content {
       match ORC.content.get(wid) {
         Ok(Some(raw)) => Some(raw.to_string()),
@@ -977,6 +1593,7 @@ impl Writ {
       viewable_by: self.viewable_by.clone(),
       commentable: self.commentable,
       is_md: self.is_md,
+      publish_at: self.publish_at,
     })
   }
 
@@ -985,8 +1602,12 @@ impl Writ {
       Some(wid) => wid,
       None => return None,
     };
-    let res: TransactionResult<i64, ()> =
-    (&ORC.votes, &ORC.writ_voters).transaction(|(votes, writ_voters)| {
+    let res: TransactionResult<i64, ()> = (
+      &ORC.votes,
+      &ORC.writ_voters,
+      &ORC.writ_rankings,
+      &ORC.writ_ranks,
+    ).transaction(|(votes, writ_voters, rankings, ranks)| {
         let vote_id = self.vote_id(usr_id);
         let wid_vec = writ_id.to_bin();
         let wid = wid_vec.as_slice();
@@ -1018,6 +1639,7 @@ impl Writ {
             }
 
             votes.insert(wid, &count.to_be_bytes())?;
+            ORC.update_writ_ranks_in_transaction(rankings, ranks, wid, |r| r.votes = count)?;
 
             return Ok(count);
           }
@@ -1038,12 +1660,16 @@ impl Writ {
           up: up.unwrap(),
         };
         writ_voters.insert(wv.id.as_bytes(), wv.try_to_vec().unwrap())?;
+        ORC.update_writ_ranks_in_transaction(rankings, ranks, wid, |r| r.votes = count)?;
 
         Ok(count)
       });
 
     match res {
-      Ok(count) => Some(count),
+      Ok(count) => {
+        forget_render(&self.id);
+        Some(count)
+      },
       Err(e) => {
         if ORC.dev_mode {
           println!("Something bad went down with voting - {:?}", e);
@@ -1087,11 +1713,12 @@ impl Writ {
     format!("{}:{}", self.kind, self.slug)
   }
 
+  // fixed width all the way through so prefixes can't run into each other and keys sort by date
   #[inline]
   pub fn date_key(&self) -> String {
     let posted = datetime_from_unix_timestamp(self.posted);
     format!(
-      "{}:{}{}{}{}:{}",
+      "{}:{:04}{:02}{:02}{:02}:{:020}",
       self.kind,
       posted.year(),
       posted.month(),
@@ -1100,6 +1727,13 @@ impl Writ {
       self.unique_id()
     )
   }
+
+  // the front of the date key, what the archive counts writs under
+  #[inline]
+  pub fn archive_month_key(&self) -> String {
+    let posted = datetime_from_unix_timestamp(self.posted);
+    format!("{}:{:04}{:02}", self.kind, posted.year(), posted.month())
+  }
 }
 
 #[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
@@ -1118,6 +1752,8 @@ pub struct EditableWrit {
   pub viewable_by: Vec<String>,
   pub commentable: bool,
   pub is_md: bool,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub publish_at: Option<i64>,
 }
 
 #[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
@@ -1131,11 +1767,29 @@ pub struct RawWrit {
   pub commentable: Option<bool>,
   pub viewable_by: Option<Vec<String>>,
   pub is_md: Option<bool>,
+  pub publish_at: Option<i64>,
+  pub force_slug: Option<bool>,
+  pub skip_sanitize: Option<bool>,
+  // only imports get to backdate new writs, it never comes in over the api
+  #[serde(skip)]
+  pub posted: Option<i64>,
 }
 
 impl RawWrit {
   pub fn commit(&self, author_id: u64) -> Result<Writ, WritError> {
+    let writ_kind = match ORC.writ_kind(&self.kind) {
+      Some(wk) => wk,
+      None => return Err(WritError::UnknownKind),
+    };
+
+    if !ORC.can_author_kind(author_id, &writ_kind) {
+      return Err(WritError::KindNotAllowed);
+    }
+
     let is_md = self.is_md.unwrap_or(true);
+    if !is_md && writ_kind.markdown_required {
+      return Err(WritError::MarkdownRequired);
+    }
     if !is_md && 
       !ORC.user_has_some_attrs(author_id, &["writer", "admin"])
         .unwrap_or(false)
@@ -1143,6 +1797,11 @@ impl RawWrit {
       return Err(WritError::NoPermNoMD);
     }
 
+    let unsanitized = !is_md && self.skip_sanitize.unwrap_or(false);
+    if unsanitized && !ORC.is_admin(author_id) {
+      return Err(WritError::NoPermUnsanitized);
+    }
+
     let tags: Vec<String> = self.tags.iter()
       .map(|t| t.trim().replace("  ", "-").replace(" ", "-").replace("--", "-"))
       .collect();
@@ -1158,7 +1817,7 @@ impl RawWrit {
           None => return Err(WritError::BadID),
         };
 
-        if wid.author != author_id {
+        if !ORC.can_edit_writ(author_id, &wid) {
           return Err(WritError::InauthenticAuthor);
         }
 
@@ -1181,24 +1840,43 @@ impl RawWrit {
       }
     };
 
-    let writ = Writ {
+    let now = unix_timestamp();
+    // a publish_at that has already passed just means publish right away
+    let publish_at = self.publish_at.and_then(|ts| (ts > now).qualify(ts));
+
+    let mut writ = Writ {
       id: writ_id.to_string(),
       slug: slug::slugify(&self.title),
-      posted: unix_timestamp(),
+      posted: self.posted.filter(|_| is_new_writ).unwrap_or(now),
       title: self.title.clone(),
       kind: self.kind.clone(),
       tags,
-      public: self.public,
-      commentable: self.commentable.unwrap_or(true),
+      public: self.public && publish_at.is_none(),
+      commentable: self.commentable.unwrap_or(writ_kind.commentable_by_default),
       viewable_by: self.viewable_by.clone().unwrap_or(vec![]),
       is_md,
+      publish_at,
+      toc: vec![],
+      word_count: 0,
+      reading_time: 0,
+      unsanitized,
+      content_hash: String::new(),
+      modified: now,
     };
 
     if is_new_writ && ORC.titles.contains_key(writ.title_key().as_bytes()).unwrap() {
       return Err(WritError::TitleTaken);
     }
 
-    let author_attrs = ORC.user_attributes(author_id);
+    // retired slugs still redirect to their old writ, so only hand them out on purpose
+    if let Some(owner) = ORC.retired_slug_owner(&writ.kind, &writ.slug) {
+      if owner.as_ref() != writ_id.to_bin().as_slice() && !self.force_slug.unwrap_or(false) {
+        return Err(WritError::SlugRetired);
+      }
+    }
+
+    // collaborators write under the author's name, so it's the author's attributes that count
+    let author_attrs = ORC.user_attributes(writ_id.author);
     if !writ.viewable_by.iter().all(|t| author_attrs.contains(t)) {
       return Err(WritError::UsedUnavailableAttributes);
     }
@@ -1219,7 +1897,27 @@ impl RawWrit {
       }
     }
 
-    let res: TransactionResult<(), ()> = (
+    let content = if is_md {
+      render_md(raw_content)
+    } else if unsanitized {
+      raw_content.to_string()
+    } else {
+      sanitize_html(raw_content)
+    };
+
+    let stats = ReadingStats::of(&content);
+    writ.word_count = stats.word_count;
+    writ.reading_time = stats.reading_time;
+    writ.toc = table_of_contents(&content);
+
+    let mut hashed = raw_content.as_bytes().to_vec();
+    hashed.extend_from_slice(content.as_bytes());
+    writ.content_hash = to_hex(&ORC.hash(&hashed)[..16]);
+
+    let search_terms = writ_search_terms(&writ.title, &writ.tags, raw_content, is_md);
+
+    // more trees than sled's tuple transactions go up to, so they're handed over as a slice
+    let trees = [
       &ORC.content,
       &ORC.raw_content,
       &ORC.titles,
@@ -1230,18 +1928,61 @@ impl RawWrit {
       &ORC.tags_index,
       &ORC.tag_counter,
       &ORC.comment_settings,
-    ).transaction(|(
-      ctn,
-      raw_ctn,
-      titles,
-      slugs,
-      dates,
-      votes,
-      writs,
-      tags_index,
-      tag_counter,
-      comment_settings,
-    )| {
+      &ORC.writ_revisions,
+      &ORC.scheduled_writs,
+      &ORC.search_index,
+      &ORC.search_terms,
+      &ORC.writ_rankings,
+      &ORC.writ_ranks,
+      &ORC.retired_slugs,
+      &ORC.slug_history,
+      &ORC.media,
+      &ORC.writ_media,
+      &ORC.public_tags,
+      &ORC.public_tag_counter,
+      &ORC.archive_months,
+      &ORC.id_counter,
+    ];
+
+    let referenced_media = media_references(raw_content);
+    let old_listing = if is_new_writ {
+      None
+    } else {
+      ORC.writ_by_id_bytes(&writ_id.to_bin()).map(|w| (w.tags, w.public))
+    };
+
+    let res: TransactionResult<Vec<String>, ()> = (&trees[..]).transaction(|trs| {
+      let (
+        ctn,
+        raw_ctn,
+        titles,
+        slugs,
+        dates,
+        votes,
+        writs,
+        tags_index,
+        tag_counter,
+        comment_settings,
+        revisions,
+        scheduled,
+        search_index,
+        writ_terms,
+        rankings,
+        ranks,
+        retired_slugs,
+        slug_history,
+        media,
+        writ_media,
+        public_tags,
+        public_tag_counter,
+        archive_months,
+        id_counter,
+      ) = (
+        &trs[0], &trs[1], &trs[2], &trs[3], &trs[4], &trs[5], &trs[6], &trs[7], &trs[8],
+        &trs[9], &trs[10], &trs[11], &trs[12], &trs[13], &trs[14], &trs[15], &trs[16], &trs[17],
+        &trs[18], &trs[19], &trs[20], &trs[21], &trs[22], &trs[23],
+      );
+
       let wid_vec = writ_id.to_bin();
       let wid = wid_vec.as_slice();
 
@@ -1249,10 +1990,8 @@ impl RawWrit {
 
       if writ.is_md {
         raw_ctn.insert(wid, raw_content.as_bytes())?;
-        ctn.insert(wid, render_md(raw_content).as_bytes())?;
-      } else {
-        ctn.insert(wid, raw_content.as_bytes())?;
       }
+      ctn.insert(wid, content.as_bytes())?;
 
       if is_new_writ {
         ORC.index_writ_tags_in_transaction(
@@ -1264,10 +2003,16 @@ impl RawWrit {
 
         titles.insert(new_writ.title_key().as_bytes(), wid)?;
         slugs.insert(new_writ.slug_key().as_bytes(), wid)?;
+        ORC.reclaim_slug_in_transaction(retired_slugs, slug_history, wid, &new_writ)?;
 
-        dates.insert(new_writ.date_key().as_bytes(), wid)?;
+        if let Some(publish_at) = new_writ.publish_at {
+          scheduled.insert(scheduled_key(publish_at, wid), wid)?;
+        } else {
+          dates.insert(new_writ.date_key().as_bytes(), wid)?;
+        }
 
         votes.insert(wid, &0i64.to_be_bytes())?;
+        ORC.insert_writ_ranks_in_transaction(rankings, ranks, wid, &WritRanks::new(new_writ.posted))?;
 
         comment_settings.insert(
           wid,
@@ -1275,6 +2020,7 @@ impl RawWrit {
             .try_to_vec()
             .unwrap(),
         )?;
+        ORC.count_archive_month_in_transaction(archive_months, None, Some(&new_writ))?;
       } else {
         let old_writ = Writ::try_from_slice(&writs.get(wid)?.unwrap()).unwrap();
 
@@ -1289,6 +2035,12 @@ impl RawWrit {
           slugs.remove(old_writ.slug_key().as_bytes())?;
           slugs.insert(new_writ.slug_key().as_bytes(), wid)?;
 
+          if new_writ.slug_key() != old_writ.slug_key() {
+            // keep the old slug around so links to it can be redirected
+            ORC.retire_slug_in_transaction(retired_slugs, slug_history, wid, &old_writ)?;
+            ORC.reclaim_slug_in_transaction(retired_slugs, slug_history, wid, &new_writ)?;
+          }
+
           let settings = CommentSettings::try_from_slice(
             &comment_settings.get(wid)?.unwrap()
           ).unwrap();
@@ -1320,15 +2072,72 @@ impl RawWrit {
           // dates.remove(old_writ.date_key().as_bytes())?;
           // dates.insert(writ.date_key().as_bytes(), writ_id)?;
         }
+
+        if new_writ.publish_at != old_writ.publish_at {
+          if let Some(old_publish_at) = old_writ.publish_at {
+            scheduled.remove(scheduled_key(old_publish_at, wid))?;
+          } else {
+            dates.remove(old_writ.date_key().as_bytes())?;
+          }
+
+          if let Some(publish_at) = new_writ.publish_at {
+            scheduled.insert(scheduled_key(publish_at, wid), wid)?;
+          } else {
+            // a scheduled writ that's being published right away
+            new_writ.posted = now;
+            dates.insert(new_writ.date_key().as_bytes(), wid)?;
+          }
+
+          let posted = new_writ.posted;
+          ORC.update_writ_ranks_in_transaction(rankings, ranks, wid, |r| r.posted = posted)?;
+        }
+
+        ORC.count_archive_month_in_transaction(archive_months, Some(&old_writ), Some(&new_writ))?;
       }
 
       writs.insert(wid, new_writ.try_to_vec().unwrap())?;
-
-      Ok(())
+      ORC.sync_public_tags_in_transaction(public_tags, public_tag_counter, wid, Some(&new_writ))?;
+
+      // the version's taken in here so an aborted commit doesn't leave a gap in the history
+      let revision = WritRevision::new(
+        ORC.next_revision_version_in_transaction(id_counter, &writ_id)?,
+        writ.title.clone(),
+        raw_content.to_string(),
+        writ.tags.clone(),
+        is_md,
+        author_id,
+      );
+      revisions.insert(
+        revision_key(wid, revision.version),
+        revision.try_to_vec().unwrap()
+      )?;
+
+      ORC.reindex_writ_search_terms_in_transaction(
+        search_index,
+        writ_terms,
+        wid,
+        &search_terms
+      )?;
+
+      ORC.relink_writ_media_in_transaction(media, writ_media, &writ.id, wid, &referenced_media)
     });
 
     match res {
-      Ok(_) => Ok(writ),
+      Ok(orphaned_media) => {
+        ORC.remove_media_files(&orphaned_media);
+        forget_render(&writ.id);
+        invalidate_sitemap();
+        match &old_listing {
+          Some((old_tags, old_public)) if *old_tags == writ.tags && *old_public == writ.public => {},
+          Some((old_tags, _)) => {
+            ORC.forget_related_writs(&writ_id.to_bin());
+            ORC.touch_related_tags(old_tags);
+            ORC.touch_related_tags(&writ.tags);
+          },
+          None => ORC.touch_related_tags(&writ.tags),
+        }
+        Ok(writ)
+      },
       Err(e) => {
         if ORC.dev_mode {
           println!("writ creation pooped out: {:?}", e);
@@ -1404,16 +2213,52 @@ pub enum WritError {
   TitleTaken,
   #[error("there was a problem interacting with the db")]
   DBIssue,
-  #[error("too many requests to writ api, chill for a bit")]
+  #[error("only authorized users may push non-markdown writs")]
   NoPermNoMD,
+  #[error("no such revision exists for this writ")]
+  NoSuchRevision,
+  #[error("that slug used to belong to another writ, force it if you really want it")]
+  SlugRetired,
+  #[error("no writ kind by that name has been registered")]
+  UnknownKind,
+  #[error("you lack the attributes needed to author this kind of writ")]
+  KindNotAllowed,
+  #[error("this kind of writ has to be written in markdown")]
+  MarkdownRequired,
+  #[error("only admins may skip sanitizing a writ's html")]
+  NoPermUnsanitized,
 /*
-  #[error("only authorized users may push non-markdown writs")]
+  #[error("too many requests to writ api, chill for a bit")]
   RateLimit,
   #[error("unknown writ error")]
   Unknown,
 */
 }
 
+impl WritError {
+  // stable names for api clients to tell errors apart by, the messages are for people
+  pub fn code(&self) -> &'static str {
+    match self {
+      WritError::BadID => "BadID",
+      WritError::NonExistentID => "NonExistentID",
+      WritError::IDGenErr => "IDGenErr",
+      WritError::InauthenticAuthor => "InauthenticAuthor",
+      WritError::InvalidTags => "InvalidTags",
+      WritError::DuplicateWrit => "DuplicateWrit",
+      WritError::UsedUnavailableAttributes => "UsedUnavailableAttributes",
+      WritError::TitleTaken => "TitleTaken",
+      WritError::DBIssue => "DBIssue",
+      WritError::NoPermNoMD => "NoPermNoMD",
+      WritError::NoSuchRevision => "NoSuchRevision",
+      WritError::SlugRetired => "SlugRetired",
+      WritError::UnknownKind => "UnknownKind",
+      WritError::KindNotAllowed => "KindNotAllowed",
+      WritError::MarkdownRequired => "MarkdownRequired",
+      WritError::NoPermUnsanitized => "NoPermUnsanitized",
+    }
+  }
+}
+
 #[get("/writ-raw-content/{id}")]
 pub async fn writ_raw_content(
   req: HttpRequest,
@@ -1422,9 +2267,15 @@ pub async fn writ_raw_content(
   // TODO: ratelimiting
   if let Some(wid) = WritID::from_str(wid.as_str()) {
     if let Some(usr) = ORC.user_by_session(&req) {
-      if wid.author == usr.id {
-        if let Ok(Some(raw_rw)) = ORC.raw_content.get(&wid.to_bin()) {
-          return crate::responses::Ok(raw_rw.to_string());
+      if ORC.can_edit_writ(usr.id, &wid) {
+        if let (Ok(Some(raw_rw)), Some(writ)) = (ORC.raw_content.get(&wid.to_bin()), ORC.writ_by_id_bytes(&wid.to_bin())) {
+          let etag = format!("\"r-{}\"", writ.content_hash);
+          let mut res = HttpResponse::Ok();
+          let res = add_validators(&mut res, &etag, Some(writ.modified), PRIVATE_CACHE_CONTROL);
+          if is_fresh(&req, &etag) {
+            return res.status(StatusCode::NOT_MODIFIED).finish().into_body();
+          }
+          return res.json(&APIResponse { ok: true, data: raw_rw.to_string() });
         } else {
           return crate::responses::NotFound("writ id didn't match anything of yours");
         }
@@ -1441,14 +2292,17 @@ pub async fn writ_raw_content(
 pub async fn post_content(
   req: HttpRequest,
   pid: web::Path<String>,
+  share: web::Query<ShareQuery>,
 ) -> HttpResponse {
   // TODO: ratelimiting
   if let Some((writ, wid)) = ORC.writ_and_id_from_str(&pid) {
-    if !writ.public {
-      if let Some(usr) = ORC.user_by_session(&req) {
-        if usr.id != wid.author {
+    let viewer = ORC.user_id_by_session(&req);
+    let shared = share.share.as_deref().map_or(false, |token| ORC.share_link_grants(token, &wid));
+    if !writ.public && !shared {
+      if let Some(usr_id) = viewer {
+        if !ORC.can_edit_writ(usr_id, &wid) {
           return crate::responses::Forbidden(
-            "You can't load the contents of private writs that aren't yours",
+            "You can't load the contents of private writs you don't author or edit",
           );
         }
       } else {
@@ -1457,7 +2311,16 @@ pub async fn post_content(
     }
 
     if let Ok(Some(raw_c)) = ORC.content.get(&wid.to_bin()) {
-      return crate::responses::Ok(raw_c.to_string());
+      ORC.record_writ_view(&req, &wid, viewer);
+
+      let etag = format!("\"c-{}\"", writ.content_hash);
+      let cache_control = if writ.public { PAGE_CACHE_CONTROL } else { PRIVATE_CACHE_CONTROL };
+      let mut res = HttpResponse::Ok();
+      let res = add_validators(&mut res, &etag, Some(writ.modified), cache_control);
+      if is_fresh(&req, &etag) {
+        return res.status(StatusCode::NOT_MODIFIED).finish().into_body();
+      }
+      return res.json(&APIResponse { ok: true, data: raw_c.to_string() });
     }
   }
 
@@ -1470,10 +2333,11 @@ pub async fn writ_query(
   query: web::Json<WritQuery>,
 ) -> HttpResponse {
   let o_usr = ORC.user_by_session(&req);
-  if let Some(writs) =
-    ORC.public_writ_query(query.into_inner(), o_usr.as_ref())
+  let paged = query.cursor.is_some();
+  if let Some(page) =
+    ORC.public_writ_query_page(query.into_inner(), o_usr.as_ref())
   {
-    return HttpResponse::Ok().json(writs);
+    return page.respond(paged);
   }
 
   crate::responses::NotFound("writ query didn't match anything, perhaps reformulate")
@@ -1485,8 +2349,9 @@ pub async fn editable_writ_query(
   query: web::Json<WritQuery>,
 ) -> HttpResponse {
   if let Some(usr) = ORC.user_by_session(&req) {
-    if let Some(writs) = ORC.editable_writ_query(query.into_inner(), &usr) {
-      return HttpResponse::Ok().json(writs);
+    let paged = query.cursor.is_some();
+    if let Some(page) = ORC.editable_writ_query_page(query.into_inner(), &usr) {
+      return page.respond(paged);
     }
   } else {
     return crate::responses::Forbidden("You can't edit things that aren't yours to edit");
@@ -1592,4 +2457,69 @@ pub async fn unvote_writ(
   }
 
   crate::responses::InternalServerError("failed to register vote")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn writ_cursors_survive_a_hex_round_trip() {
+    let cursors = vec![
+      WritCursor::Offset(0),
+      WritCursor::Offset(u64::MAX),
+      WritCursor::Key(vec![]),
+      WritCursor::Key(b"post\x00\x00\x00\x00\x00\x00\x00\x01".to_vec()),
+    ];
+    for cursor in cursors {
+      let encoded = cursor.encode();
+      assert!(encoded.chars().all(|c| c.is_ascii_hexdigit()));
+      assert_eq!(WritCursor::decode(&encoded), Some(cursor));
+    }
+  }
+
+  #[test]
+  fn malformed_writ_cursors_are_refused() {
+    assert_eq!(WritCursor::decode("0"), None);
+    assert_eq!(WritCursor::decode("zz"), None);
+    // an enum tag that doesn't exist, and an offset missing its bytes
+    assert_eq!(WritCursor::decode("05"), None);
+    assert_eq!(WritCursor::decode("00"), None);
+  }
+
+  fn writ_posted_at(posted: i64) -> Writ {
+    Writ {
+      id: "post:1:7".to_string(),
+      title: "Padding".to_string(),
+      slug: "padding".to_string(),
+      kind: "post".to_string(),
+      tags: vec![],
+      posted,
+      public: true,
+      viewable_by: vec![],
+      commentable: true,
+      is_md: true,
+      publish_at: None,
+      toc: vec![],
+      word_count: 0,
+      reading_time: 0,
+      unsanitized: false,
+      content_hash: String::new(),
+      modified: posted,
+    }
+  }
+
+  #[test]
+  fn date_keys_are_zero_padded() {
+    // 2021-01-12 05:00 and 2021-11-02 17:00 utc, which both came out as 2021112 unpadded
+    let january = writ_posted_at(1610427600);
+    let november = writ_posted_at(1635872400);
+
+    assert_eq!(january.date_key(), "post:2021011205:00000000000000000007");
+    assert_eq!(november.date_key(), "post:2021110217:00000000000000000007");
+    assert!(january.date_key() < november.date_key());
+
+    assert_eq!(january.archive_month_key(), "post:202101");
+    assert!(january.date_key().starts_with(&january.archive_month_key()));
+  }
 }
\ No newline at end of file